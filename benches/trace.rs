@@ -0,0 +1,48 @@
+//! Time and peak heap use of tracing `assets/map.bmp`. Run with `cargo bench --bench trace`
+
+use std::{alloc::{GlobalAlloc, Layout, System}, sync::atomic::{AtomicUsize, Ordering}, time::Instant};
+
+use bmpoly::polygon::try_load_polygons;
+
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(current, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+const RUNS: u32 = 20;
+
+fn main() {
+    let img = bmp::open("assets/map.bmp").unwrap();
+
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let polygons = try_load_polygons(img.clone()).unwrap();
+    let peak = PEAK.load(Ordering::Relaxed) - baseline;
+
+    let before = Instant::now();
+    for _ in 0..RUNS {
+        try_load_polygons(img.clone()).unwrap();
+    }
+    let elapsed = before.elapsed() / RUNS;
+
+    println!();
+    println!("map.bmp: {}x{}, {} polygons", img.get_width(), img.get_height(), polygons.len());
+    println!("peak heap: {:.1} MiB", peak as f64 / (1024.0 * 1024.0));
+    println!("time per trace: {:.1}ms", elapsed.as_secs_f64() * 1000.0);
+}
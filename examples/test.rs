@@ -26,6 +26,7 @@ fn setup(
     border_segments.add(BorderSegment {
         province_id: 1,
         neighbor_id: 2,
+        ..default()
     });
 }
 
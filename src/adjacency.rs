@@ -0,0 +1,67 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{eu4::{Definitions, TerrainType}, source::PixelSource, topology::{load_topology, Topology}, Error};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbor {
+    pub color: (u8, u8, u8),
+    /// Province ID from colors.txt, if the RGB value of the color is defined there
+    pub id: Option<u32>,
+    pub terrain: TerrainType,
+    /// Length of the shared border in pixel edges
+    pub border_length: u32,
+}
+
+/// Which regions share a border, and how long that border is
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdjacencyGraph {
+    /// Neighbors of every color, sorted by their color
+    pub neighbors: BTreeMap<(u8, u8, u8), Vec<Neighbor>>,
+    pub(crate) ids: HashMap<u32, (u8, u8, u8)>,
+}
+
+impl AdjacencyGraph {
+    /// Regions touching only at a corner are not neighbors. Colors are matched to `definitions` by their RGB value,
+    /// so maps traced on palette indices get the right IDs and terrain
+    pub fn new(topology: &Topology, definitions: &Definitions) -> Self {
+        let mut lengths: BTreeMap<_, BTreeMap<_, u32>> = BTreeMap::new();
+        for arc in &topology.arcs {
+            if let (Some(left), Some(right)) = (arc.left, arc.right) {
+                *lengths.entry(left).or_default().entry(right).or_default() += arc.length;
+                *lengths.entry(right).or_default().entry(left).or_default() += arc.length;
+            }
+        }
+
+        let neighbors = lengths.into_iter().map(|(color, neighbors)| {
+            let neighbors = neighbors.into_iter().map(|(neighbor, border_length)| Neighbor {
+                color: neighbor,
+                id: definitions.get(topology.rgb(neighbor)).map(|def| def.id),
+                terrain: definitions.terrain(topology.rgb(neighbor)),
+                border_length,
+            }).collect();
+            (color, neighbors)
+        }).collect();
+
+        let ids = topology.rings.iter()
+            .filter_map(|ring| definitions.get(topology.rgb(ring.color)).map(|def| (def.id, ring.color)))
+            .collect();
+
+        AdjacencyGraph { neighbors, ids }
+    }
+
+    pub fn neighbors(&self, color: (u8, u8, u8)) -> &[Neighbor] {
+        self.neighbors.get(&color).map(|n| n.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn neighbors_of_id(&self, id: u32) -> &[Neighbor] {
+        match self.ids.get(&id) {
+            Some(color) => self.neighbors(*color),
+            None => &[],
+        }
+    }
+}
+
+/// Builds the adjacency graph of a bitmap, without triangulating anything
+pub fn load_adjacency(img: impl PixelSource, definitions: &Definitions) -> Result<AdjacencyGraph, Error> {
+    Ok(AdjacencyGraph::new(&load_topology(img)?, definitions))
+}
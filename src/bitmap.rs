@@ -0,0 +1,229 @@
+use std::{fmt, fs, io, path::Path};
+
+use bmp::{Image, Pixel};
+
+use crate::source::PixelSource;
+
+/// A decoded BMP file. Indexed files keep their palette, so regions are traced on palette indices
+#[derive(Debug, Clone)]
+pub enum Bitmap {
+    Rgb(Image),
+    Indexed(PaletteImage),
+}
+
+/// An indexed image. Palette index `i` is traced as the color `(0, 0, i)`,
+/// so palette entries with the same RGB value still make separate regions
+#[derive(Debug, Clone)]
+pub struct PaletteImage {
+    width: u32,
+    height: u32,
+    /// Row by row from the top
+    indices: Vec<u8>,
+    palette: Vec<(u8, u8, u8)>,
+}
+
+impl PaletteImage {
+    pub fn palette(&self) -> &[(u8, u8, u8)] {
+        &self.palette
+    }
+
+    pub fn index(&self, x: u32, y: u32) -> u8 {
+        self.indices[(y * self.width + x) as usize]
+    }
+}
+
+impl PixelSource for PaletteImage {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        (0, 0, self.index(x, y))
+    }
+
+    /// Indices outside the palette are black
+    fn rgb(&self, (_, _, index): (u8, u8, u8)) -> (u8, u8, u8) {
+        self.palette.get(index as usize).copied().unwrap_or((0, 0, 0))
+    }
+}
+
+impl Bitmap {
+    /// The palette of indexed images
+    pub fn palette(&self) -> Option<&[(u8, u8, u8)]> {
+        match self {
+            Bitmap::Rgb(_) => None,
+            Bitmap::Indexed(img) => Some(img.palette()),
+        }
+    }
+}
+
+impl PixelSource for Bitmap {
+    fn width(&self) -> u32 {
+        match self {
+            Bitmap::Rgb(img) => img.get_width(),
+            Bitmap::Indexed(img) => img.width,
+        }
+    }
+
+    fn height(&self) -> u32 {
+        match self {
+            Bitmap::Rgb(img) => img.get_height(),
+            Bitmap::Indexed(img) => img.height,
+        }
+    }
+
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        match self {
+            Bitmap::Rgb(img) => img.color(x, y),
+            Bitmap::Indexed(img) => img.color(x, y),
+        }
+    }
+
+    fn rgb(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        match self {
+            Bitmap::Rgb(_) => color,
+            Bitmap::Indexed(img) => img.rgb(color),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BitmapError {
+    Io(io::Error),
+    Bmp(bmp::BmpError),
+    /// The file ends before the header or pixel data it describes
+    Truncated,
+    /// Compressed files, bit depths other than 1, 4, 8, 16 and 24, and 16 bit channel masks wider than the pixels
+    Unsupported { bits_per_pixel: u16, compression: u32 },
+}
+
+impl fmt::Display for BitmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitmapError::Io(err) => write!(f, "{}", err),
+            BitmapError::Bmp(err) => write!(f, "{}", err),
+            BitmapError::Truncated => write!(f, "bitmap is truncated"),
+            BitmapError::Unsupported { bits_per_pixel, compression } => {
+                write!(f, "unsupported bitmap: {} bits per pixel, compression {}", bits_per_pixel, compression)
+            },
+        }
+    }
+}
+
+impl std::error::Error for BitmapError {}
+
+impl From<io::Error> for BitmapError {
+    fn from(err: io::Error) -> Self {
+        BitmapError::Io(err)
+    }
+}
+
+impl From<bmp::BmpError> for BitmapError {
+    fn from(err: bmp::BmpError) -> Self {
+        BitmapError::Bmp(err)
+    }
+}
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<u16, BitmapError> {
+    bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or(BitmapError::Truncated)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, BitmapError> {
+    bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or(BitmapError::Truncated)
+}
+
+// Scales a channel of up to 16 bits up to eight
+fn expand_channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = mask.count_ones();
+    let value = (value & mask) >> mask.trailing_zeros();
+    ((value * 255 + ((1 << bits) - 1) / 2) / ((1 << bits) - 1)) as u8
+}
+
+/// Decodes a BMP file. 1, 4 and 8 bit files become [`Bitmap::Indexed`], and 16 bit ones are expanded to RGB.
+/// Everything else is left to the `bmp` crate
+pub fn from_bytes(bytes: &[u8]) -> Result<Bitmap, BitmapError> {
+    let data_offset = read_u32(bytes, 10)? as usize;
+    let header_size = read_u32(bytes, 14)? as usize;
+    let width = read_u32(bytes, 18)? as i32;
+    let height = read_u32(bytes, 22)? as i32;
+    let bits_per_pixel = read_u16(bytes, 28)?;
+    let compression = read_u32(bytes, 30)?;
+    let colors_used = read_u32(bytes, 46)? as usize;
+
+    if !matches!(bits_per_pixel, 1 | 4 | 8 | 16) || header_size < 40 {
+        return Ok(Bitmap::Rgb(bmp::from_reader(&mut io::Cursor::new(bytes))?));
+    }
+    let supported = compression == BI_RGB || (bits_per_pixel == 16 && compression == BI_BITFIELDS);
+    if !supported || width <= 0 || height == 0 {
+        return Err(BitmapError::Unsupported { bits_per_pixel, compression });
+    }
+
+    let (width, top_down) = (width as u32, height < 0);
+    let height = height.unsigned_abs();
+    let stride = (width as usize * bits_per_pixel as usize).div_ceil(32) * 4;
+    if bytes.len() < data_offset + stride * height as usize {
+        return Err(BitmapError::Truncated);
+    }
+    let row = |y: u32| {
+        let stored = if top_down { y } else { height - y - 1 };
+        &bytes[data_offset + stored as usize * stride..][..stride]
+    };
+
+    if bits_per_pixel == 16 {
+        // 5 bits per channel unless the header says otherwise
+        let (r, g, b) = if compression == BI_BITFIELDS {
+            (read_u32(bytes, 54)?, read_u32(bytes, 58)?, read_u32(bytes, 62)?)
+        } else {
+            (0x7c00, 0x03e0, 0x001f)
+        };
+        if (r | g | b) > 0xffff {
+            return Err(BitmapError::Unsupported { bits_per_pixel, compression });
+        }
+        let mut img = Image::new(width, height);
+        for y in 0..height {
+            let row = row(y);
+            for x in 0..width {
+                let value = u16::from_le_bytes([row[x as usize * 2], row[x as usize * 2 + 1]]) as u32;
+                img.set_pixel(x, y, Pixel::new(expand_channel(value, r), expand_channel(value, g), expand_channel(value, b)));
+            }
+        }
+        return Ok(Bitmap::Rgb(img));
+    }
+
+    let entries = if colors_used == 0 { 1 << bits_per_pixel } else { colors_used };
+    let palette_offset = 14 + header_size;
+    let palette = bytes.get(palette_offset..palette_offset + entries * 4)
+        .ok_or(BitmapError::Truncated)?
+        .chunks(4)
+        .map(|bgra| (bgra[2], bgra[1], bgra[0]))
+        .collect();
+
+    let per_byte = 8 / bits_per_pixel as u32;
+    let mask = (1u16 << bits_per_pixel) - 1;
+    let mut indices = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        let row = row(y);
+        for x in 0..width {
+            // The leftmost pixel is in the highest bits
+            let byte = row[(x / per_byte) as usize] as u16;
+            let shift = (per_byte - 1 - x % per_byte) * bits_per_pixel as u32;
+            indices.push(((byte >> shift) & mask) as u8);
+        }
+    }
+
+    Ok(Bitmap::Indexed(PaletteImage { width, height, indices, palette }))
+}
+
+pub fn open(path: impl AsRef<Path>) -> Result<Bitmap, BitmapError> {
+    from_bytes(&fs::read(path)?)
+}
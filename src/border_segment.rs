@@ -3,7 +3,9 @@ use bevy::prelude::*;
 #[derive(Asset, TypePath, Resource)]
 pub struct BorderSegment {
     pub province_id: u32,
-    pub neighbor_id: u32
+    pub neighbor_id: u32,
+    /// Length of the shared border in pixel edges
+    pub length: u32,
 }
 
 impl Default for BorderSegment {
@@ -11,6 +13,7 @@ impl Default for BorderSegment {
         Self {
             province_id: 0,
             neighbor_id: 0,
+            length: 0,
         }
     }
 }
@@ -0,0 +1,280 @@
+use std::{collections::{BTreeMap, HashMap}, fs, io, path::Path};
+
+use crate::{adjacency::{AdjacencyGraph, Neighbor}, eu4::TerrainType, polygon::{BoundingBox, Polygon, PolygonPart}, topology::ArcRef};
+
+const MAGIC: &[u8; 4] = b"BMPC";
+/// Bump whenever the layout below, or what the tracer produces, changes. Files of any other version are ignored
+pub const CACHE_VERSION: u32 = 3;
+
+/// Everything traced from a map that is worth keeping between runs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MapCache {
+    /// With their triangulations and borders
+    pub polygons: Vec<Polygon>,
+    pub adjacency: AdjacencyGraph,
+}
+
+// FNV-1a, which unlike the std hasher is the same on every platform and Rust version
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Hash of the source bitmap and the colors.txt, seas.txt and lakes.txt in `definitions_dir`, which the cache is only valid for
+pub fn content_hash(bitmap: &[u8], definitions_dir: impl AsRef<Path>) -> io::Result<u64> {
+    let mut hash = 0xcbf29ce484222325;
+    hash = fnv1a(hash, &(bitmap.len() as u64).to_le_bytes());
+    hash = fnv1a(hash, bitmap);
+    for file in ["colors.txt", "seas.txt", "lakes.txt"] {
+        let bytes = fs::read(definitions_dir.as_ref().join(file))?;
+        hash = fnv1a(hash, &(bytes.len() as u64).to_le_bytes());
+        hash = fnv1a(hash, &bytes);
+    }
+    Ok(hash)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    fn color(&mut self, (r, g, b): (u8, u8, u8)) {
+        self.0.extend([r, g, b]);
+    }
+
+    fn terrain(&mut self, terrain: Option<TerrainType>) {
+        self.u8(match terrain {
+            None => 0,
+            Some(TerrainType::Land) => 1,
+            Some(TerrainType::Sea) => 2,
+            Some(TerrainType::Lake) => 3,
+        });
+    }
+
+    fn point(&mut self, (x, y): (f32, f32)) {
+        self.f32(x);
+        self.f32(y);
+    }
+
+    fn ring(&mut self, ring: &[[f32; 3]]) {
+        self.len(ring.len());
+        for v in ring {
+            v.iter().for_each(|c| self.f32(*c));
+        }
+    }
+
+    fn polygon(&mut self, poly: &Polygon) {
+        self.color(poly.source_color);
+        self.color(poly.rgb);
+        self.terrain(poly.terrain);
+        self.ring(&poly.vertices);
+        self.len(poly.border_vertices.len());
+        poly.border_vertices.iter().for_each(|ring| self.ring(ring));
+        self.len(poly.arc_rings.len());
+        for ring in &poly.arc_rings {
+            self.len(ring.len());
+            for arc in ring {
+                self.len(arc.arc);
+                self.u8(arc.reversed as u8);
+            }
+        }
+        self.len(poly.indicies.len());
+        poly.indicies.iter().for_each(|i| self.u32(*i));
+        self.len(poly.parts.len());
+        for part in &poly.parts {
+            self.ring(&part.outer);
+            self.len(part.holes.len());
+            part.holes.iter().for_each(|hole| self.ring(hole));
+            self.f32(part.area);
+            self.point(part.bounds.min);
+            self.point(part.bounds.max);
+            self.len(part.indices.start);
+            self.len(part.indices.end);
+        }
+    }
+
+    fn adjacency(&mut self, adjacency: &AdjacencyGraph) {
+        self.len(adjacency.neighbors.len());
+        for (color, neighbors) in &adjacency.neighbors {
+            self.color(*color);
+            self.len(neighbors.len());
+            for neighbor in neighbors {
+                self.color(neighbor.color);
+                match neighbor.id {
+                    Some(id) => { self.u8(1); self.u32(id); },
+                    None => self.u8(0),
+                }
+                self.terrain(Some(neighbor.terrain));
+                self.u32(neighbor.border_length);
+            }
+        }
+        // Sorted, so the same graph always writes the same bytes
+        let ids: BTreeMap<&u32, &(u8, u8, u8)> = adjacency.ids.iter().collect();
+        self.len(ids.len());
+        for (id, color) in ids {
+            self.u32(*id);
+            self.color(*color);
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid map cache: {}", message))
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.0.len() < N {
+            return Err(invalid("truncated"));
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+
+    /// A length of items at least `item_size` bytes each, checked against what is left so corrupt files can't allocate much
+    fn len(&mut self, item_size: usize) -> io::Result<usize> {
+        let len = self.u32()? as usize;
+        if len * item_size > self.0.len() {
+            return Err(invalid("truncated"));
+        }
+        Ok(len)
+    }
+
+    fn index(&mut self) -> io::Result<usize> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn color(&mut self) -> io::Result<(u8, u8, u8)> {
+        let [r, g, b] = self.bytes()?;
+        Ok((r, g, b))
+    }
+
+    fn terrain(&mut self) -> io::Result<Option<TerrainType>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(TerrainType::Land)),
+            2 => Ok(Some(TerrainType::Sea)),
+            3 => Ok(Some(TerrainType::Lake)),
+            _ => Err(invalid("unknown terrain")),
+        }
+    }
+
+    fn point(&mut self) -> io::Result<(f32, f32)> {
+        Ok((self.f32()?, self.f32()?))
+    }
+
+    fn ring(&mut self) -> io::Result<Vec<[f32; 3]>> {
+        (0..self.len(12)?).map(|_| Ok([self.f32()?, self.f32()?, self.f32()?])).collect()
+    }
+
+    fn polygon(&mut self) -> io::Result<Polygon> {
+        let source_color = self.color()?;
+        let rgb = self.color()?;
+        let terrain = self.terrain()?;
+        let vertices = self.ring()?;
+        let border_vertices = (0..self.len(4)?).map(|_| self.ring()).collect::<io::Result<_>>()?;
+        let arc_rings = (0..self.len(4)?)
+            .map(|_| (0..self.len(5)?).map(|_| Ok(ArcRef { arc: self.index()?, reversed: self.u8()? != 0 })).collect())
+            .collect::<io::Result<_>>()?;
+        let indicies = (0..self.len(4)?).map(|_| self.u32()).collect::<io::Result<_>>()?;
+        let parts = (0..self.len(36)?)
+            .map(|_| Ok(PolygonPart {
+                outer: self.ring()?,
+                holes: (0..self.len(4)?).map(|_| self.ring()).collect::<io::Result<_>>()?,
+                area: self.f32()?,
+                bounds: BoundingBox { min: self.point()?, max: self.point()? },
+                indices: self.index()?..self.index()?,
+            }))
+            .collect::<io::Result<_>>()?;
+        Ok(Polygon { terrain, source_color, rgb, vertices, border_vertices, arc_rings, indicies, parts })
+    }
+
+    fn adjacency(&mut self) -> io::Result<AdjacencyGraph> {
+        let mut neighbors = BTreeMap::new();
+        for _ in 0..self.len(7)? {
+            let color = self.color()?;
+            let list = (0..self.len(9)?)
+                .map(|_| Ok(Neighbor {
+                    color: self.color()?,
+                    id: if self.u8()? != 0 { Some(self.u32()?) } else { None },
+                    terrain: self.terrain()?.ok_or_else(|| invalid("neighbor without terrain"))?,
+                    border_length: self.u32()?,
+                }))
+                .collect::<io::Result<_>>()?;
+            neighbors.insert(color, list);
+        }
+        let ids: HashMap<u32, (u8, u8, u8)> = (0..self.len(7)?).map(|_| Ok((self.u32()?, self.color()?))).collect::<io::Result<_>>()?;
+        Ok(AdjacencyGraph { neighbors, ids })
+    }
+}
+
+/// Writes `cache` for the sources with `hash`, see [`content_hash`]
+pub fn save_cache(path: impl AsRef<Path>, hash: u64, cache: &MapCache) -> io::Result<()> {
+    let mut w = Writer(Vec::new());
+    w.0.extend(MAGIC);
+    w.u32(CACHE_VERSION);
+    w.0.extend(hash.to_le_bytes());
+    w.len(cache.polygons.len());
+    cache.polygons.iter().for_each(|poly| w.polygon(poly));
+    w.adjacency(&cache.adjacency);
+    fs::write(path, w.0)
+}
+
+/// Reads a cache written by [`save_cache`]. `None` if there is no cache, or it is of another version or for other sources than `hash`
+pub fn load_cache(path: impl AsRef<Path>, hash: u64) -> io::Result<Option<MapCache>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let mut r = Reader(&bytes);
+    if &r.bytes::<4>()? != MAGIC {
+        return Err(invalid("not a map cache"));
+    }
+    if r.u32()? != CACHE_VERSION || r.u64()? != hash {
+        return Ok(None);
+    }
+
+    let polygons = (0..r.len(4)?).map(|_| r.polygon()).collect::<io::Result<_>>()?;
+    let adjacency = r.adjacency()?;
+    if !r.0.is_empty() {
+        return Err(invalid("trailing bytes"));
+    }
+    Ok(Some(MapCache { polygons, adjacency }))
+}
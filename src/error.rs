@@ -0,0 +1,34 @@
+use std::fmt;
+
+/// Errors produced while turning a bitmap into polygons.
+/// Positions are pixel coordinates in the source image (origin top-left).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The image has no pixels.
+    EmptyImage,
+    /// A hole was traced, but no outer ring of the same color contains it.
+    HoleWithoutParent { color: (u8, u8, u8), position: (u32, u32) },
+    /// earcutr failed to triangulate a ring and its holes.
+    Triangulation { color: (u8, u8, u8), position: (u32, u32) },
+    /// A region whose outline is too small or malformed to be a polygon.
+    DegenerateRegion { color: (u8, u8, u8), position: (u32, u32) },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EmptyImage => write!(f, "image is empty"),
+            Error::HoleWithoutParent { color, position } => {
+                write!(f, "hole in color {:?} at pixel {:?} is not inside any region of that color", color, position)
+            },
+            Error::Triangulation { color, position } => {
+                write!(f, "failed to triangulate color {:?} at pixel {:?}", color, position)
+            },
+            Error::DegenerateRegion { color, position } => {
+                write!(f, "degenerate region of color {:?} at pixel {:?}", color, position)
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
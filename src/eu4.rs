@@ -1,61 +1,95 @@
-use std::fs;
+use std::{fs, io, path::Path};
 
-use bevy::utils::hashbrown::HashMap;
+use std::collections::HashMap;
 
-use crate::{polygon::*, SEA_MATERIAL_HANDLE, LAND_MATERIAL_HANDLE};
+use crate::polygon::*;
 
-enum TerrainType {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TerrainType {
     Sea,
     Lake,
     Land,
 }
 
-pub fn color_polys(polys: &mut Vec<Polygon>) {
-    let seas: Vec<u32> = fs::read_to_string("seas.txt").unwrap().split_whitespace().map(|p| p.parse::<u32>().unwrap()).collect();
-    let lakes: Vec<u32> = fs::read_to_string("lakes.txt").unwrap().split_whitespace().map(|p| p.parse::<u32>().unwrap()).collect();
-    let mut colors: HashMap<(u8, u8, u8), TerrainType> = HashMap::new();
+#[derive(Debug, Clone)]
+pub struct ProvinceDefinition {
+    pub id: u32,
+    pub name: String,
+    pub terrain: TerrainType,
+}
 
-    let clr_str = fs::read_to_string("colors.txt").unwrap();
-    let raw_colors = clr_str.split_whitespace();
-    for color in raw_colors {
-        if !color.chars().next().unwrap().is_numeric() {
-            continue;
-        }
-        let mut color = color.split(";");
-        let id = color.next().unwrap().parse::<u32>().unwrap();
-        let r = color.next().unwrap().parse::<u8>().unwrap();
-        let g = color.next().unwrap().parse::<u8>().unwrap();
-        let b = color.next().unwrap().parse::<u8>().unwrap();
-        
-        let terrain = {
-            if seas.contains(&id) {
-                TerrainType::Sea
-            } else if lakes.contains(&id) {
-                TerrainType::Lake
-            } else {
-                TerrainType::Land
+/// Provinces as defined by colors.txt, with their terrain from seas.txt and lakes.txt
+#[derive(Debug, Clone, Default)]
+pub struct Definitions {
+    pub provinces: HashMap<(u8, u8, u8), ProvinceDefinition>,
+}
+
+fn invalid(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid province definition: {}", line))
+}
+
+fn read_ids(path: &Path) -> io::Result<Vec<u32>> {
+    fs::read_to_string(path)?
+        .split_whitespace()
+        .map(|p| p.parse::<u32>().map_err(|_| invalid(p)))
+        .collect()
+}
+
+impl Definitions {
+    /// Reads colors.txt, seas.txt and lakes.txt from the given directory
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let seas = read_ids(&dir.join("seas.txt"))?;
+        let lakes = read_ids(&dir.join("lakes.txt"))?;
+        let mut provinces = HashMap::new();
+
+        let clr_str = fs::read_to_string(dir.join("colors.txt"))?;
+        for line in clr_str.lines() {
+            if !line.chars().next().is_some_and(|c| c.is_numeric()) {
+                continue;
             }
-        };
-        colors.insert((r, g, b), terrain);
+            let mut fields = line.split(';');
+            let mut number = || fields.next().and_then(|f| f.trim().parse::<u32>().ok()).ok_or_else(|| invalid(line));
+            let id = number()?;
+            let (r, g, b) = (number()?, number()?, number()?);
+            if r > 255 || g > 255 || b > 255 {
+                return Err(invalid(line));
+            }
+            let name = fields.next().unwrap_or_default().trim().to_string();
+
+            let terrain = {
+                if seas.contains(&id) {
+                    TerrainType::Sea
+                } else if lakes.contains(&id) {
+                    TerrainType::Lake
+                } else {
+                    TerrainType::Land
+                }
+            };
+            provinces.insert((r as u8, g as u8, b as u8), ProvinceDefinition { id, name, terrain });
+        }
+
+        Ok(Definitions { provinces })
+    }
+
+    pub fn get(&self, color: (u8, u8, u8)) -> Option<&ProvinceDefinition> {
+        self.provinces.get(&color)
     }
 
+    /// Colors without a definition count as land
+    pub fn terrain(&self, color: (u8, u8, u8)) -> TerrainType {
+        self.get(color).map(|def| def.terrain).unwrap_or(TerrainType::Land)
+    }
+}
+
+pub fn color_polys(polys: &mut [Polygon]) {
+    let definitions = Definitions::load(".").unwrap();
+    color_polys_with(polys, &definitions);
+}
+
+/// Classifies by the RGB value of each polygon, so polygons traced on palette indices still match colors.txt
+pub fn color_polys_with(polys: &mut [Polygon], definitions: &Definitions) {
     for poly in polys {
-        let color;
-        if let Some(terrain) = colors.get(&poly.source_color) {
-            match terrain {
-                TerrainType::Sea => {
-                    color = SEA_MATERIAL_HANDLE;
-                },
-                TerrainType::Lake => {
-                    color = SEA_MATERIAL_HANDLE;
-                },
-                TerrainType::Land => {
-                    color = LAND_MATERIAL_HANDLE;
-                },
-            }
-        } else {
-            color = LAND_MATERIAL_HANDLE;
-        }
-        poly.mat_handle = color;
+        poly.terrain = Some(definitions.terrain(poly.rgb));
     }
-}
\ No newline at end of file
+}
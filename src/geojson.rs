@@ -0,0 +1,110 @@
+use std::{fmt::Write as _, io};
+
+use crate::{eu4::{Definitions, TerrainType}, geometry::signed_area, polygon::Polygon};
+
+/// `"land"`, `"sea"` or `"lake"`
+pub fn terrain_name(terrain: TerrainType) -> &'static str {
+    match terrain {
+        TerrainType::Land => "land",
+        TerrainType::Sea => "sea",
+        TerrainType::Lake => "lake",
+    }
+}
+
+pub(crate) fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// A closed linear ring, turned to run counter-clockwise, or clockwise for holes
+fn write_ring(out: &mut String, ring: &[[f32; 3]], clockwise: bool) {
+    let mut points: Vec<[f32; 3]> = ring.to_vec();
+    if (signed_area(&points) < 0.0) != clockwise {
+        points.reverse();
+    }
+    if let Some(first) = points.first().copied() {
+        points.push(first);
+    }
+
+    out.push('[');
+    for (i, p) in points.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "[{},{}]", p[0], p[1]);
+    }
+    out.push(']');
+}
+
+fn write_feature(out: &mut String, poly: &Polygon, definitions: &Definitions) {
+    let definition = definitions.get(poly.rgb);
+    let (r, g, b) = poly.rgb;
+
+    out.push_str("{\"type\":\"Feature\",");
+    if let Some(def) = definition {
+        let _ = write!(out, "\"id\":{},", def.id);
+    }
+
+    out.push_str("\"geometry\":{\"type\":\"MultiPolygon\",\"coordinates\":[");
+    for (i, part) in poly.parts.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push('[');
+        write_ring(out, &part.outer, false);
+        for hole in &part.holes {
+            out.push(',');
+            write_ring(out, hole, true);
+        }
+        out.push(']');
+    }
+    out.push_str("]},");
+
+    let _ = write!(out, "\"properties\":{{\"color\":\"#{:02x}{:02x}{:02x}\",\"id\":", r, g, b);
+    match definition {
+        Some(def) => { let _ = write!(out, "{}", def.id); },
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"name\":");
+    match definition {
+        Some(def) => json_string(out, &def.name),
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"terrain\":");
+    match definition.map(|def| def.terrain).or(poly.terrain) {
+        Some(terrain) => json_string(out, terrain_name(terrain)),
+        None => out.push_str("null"),
+    }
+    out.push_str("}}");
+}
+
+/// A GeoJSON `FeatureCollection` with a `MultiPolygon` feature per polygon, in the coordinates of the vertices.
+///
+/// Outer rings run counter-clockwise and holes clockwise, as RFC 7946 asks. Properties are the region's RGB value as `"#rrggbb"`,
+/// and the province `id`, `name` and `terrain` from `definitions`, which are `null` for colors without a definition
+pub fn to_geojson(polygons: &[Polygon], definitions: &Definitions) -> String {
+    let mut out = String::from("{\"type\":\"FeatureCollection\",\"features\":[");
+    for (i, poly) in polygons.iter().enumerate() {
+        if i > 0 {
+            out.push_str(",\n");
+        }
+        write_feature(&mut out, poly, definitions);
+    }
+    out.push_str("]}\n");
+    out
+}
+
+pub fn write_geojson(mut writer: impl io::Write, polygons: &[Polygon], definitions: &Definitions) -> io::Result<()> {
+    writer.write_all(to_geojson(polygons, definitions).as_bytes())
+}
@@ -0,0 +1,64 @@
+/// A vertex of a ring, in any of the point types the tracers use
+pub(crate) trait RingPoint: Copy {
+    fn xy(self) -> (f64, f64);
+}
+
+impl RingPoint for (f32, f32) {
+    fn xy(self) -> (f64, f64) {
+        (self.0 as f64, self.1 as f64)
+    }
+}
+
+impl RingPoint for [f32; 3] {
+    fn xy(self) -> (f64, f64) {
+        (self[0] as f64, self[1] as f64)
+    }
+}
+
+/// Area enclosed by a ring, positive when it runs counter-clockwise.
+/// Summed in f64, as the products of f32 coordinates far from the origin cancel each other out
+pub(crate) fn signed_area<P: RingPoint>(ring: &[P]) -> f64 {
+    let mut area = 0.0;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i].xy(), ring[(i + 1) % ring.len()].xy());
+        area += a.0 * b.1 - b.0 * a.1;
+    }
+    area / 2.0
+}
+
+/// Area enclosed by a ring, whichever way it runs
+pub(crate) fn area<P: RingPoint>(ring: &[P]) -> f64 {
+    signed_area(ring).abs()
+}
+
+/// Even-odd test against closed rings, such as the outer ring and holes of a part
+pub(crate) fn contains<P: RingPoint, R: AsRef<[P]>>(rings: impl IntoIterator<Item = R>, point: (f32, f32)) -> bool {
+    let (x, y) = (point.0 as f64, point.1 as f64);
+    let mut inside = false;
+    for ring in rings {
+        let ring = ring.as_ref();
+        for i in 0..ring.len() {
+            let (a, b) = (ring[i].xy(), ring[(i + 1) % ring.len()].xy());
+            if (a.1 > y) != (b.1 > y) && x < (b.0 - a.0) * (y - a.1) / (b.1 - a.1) + a.0 {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_area_far_from_origin() {
+        // Inside the extent of a 5632x2048 map, where f32 sums came out as zero or negative
+        for (x, y) in [(3000.0, 500.0), (4999.0, 1999.0), (5631.0, 2047.0)] {
+            let ring = [(x - 0.5, y - 0.5), (x + 0.5, y - 0.5), (x + 0.5, y + 0.5), (x - 0.5, y + 0.5)];
+            assert_eq!(signed_area(&ring), 1.0);
+            let reversed: Vec<(f32, f32)> = ring.iter().rev().copied().collect();
+            assert_eq!(signed_area(&reversed), -1.0);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{fmt::Write as _, io};
+
+use crate::{eu4::Definitions, geojson::{json_string, terrain_name}, polygon::Polygon};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+// glTF colors are linear
+fn linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// The document up to its buffers, and the binary buffer. [`finish`] closes the document
+fn build(polygons: &[Polygon], definitions: &Definitions) -> (String, Vec<u8>) {
+    let mut bin: Vec<u8> = Vec::new();
+    let (mut nodes, mut meshes, mut materials, mut accessors, mut views) = (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+
+    for poly in polygons.iter().filter(|poly| !poly.vertices.is_empty() && !poly.indicies.is_empty()) {
+        let i = meshes.len();
+        let definition = definitions.get(poly.rgb);
+        let (r, g, b) = poly.rgb;
+
+        let (min, max) = poly.vertices.iter().fold(([f32::MAX; 3], [f32::MIN; 3]), |(lo, hi), v| {
+            ([lo[0].min(v[0]), lo[1].min(v[1]), lo[2].min(v[2])], [hi[0].max(v[0]), hi[1].max(v[1]), hi[2].max(v[2])])
+        });
+        let offset = bin.len();
+        bin.extend(poly.vertices.iter().flatten().flat_map(|c| c.to_le_bytes()));
+        views.push(format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}", offset, bin.len() - offset, ARRAY_BUFFER));
+        accessors.push(format!(
+            "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+            2 * i, FLOAT, poly.vertices.len(), min[0], min[1], min[2], max[0], max[1], max[2],
+        ));
+
+        let offset = bin.len();
+        bin.extend(poly.indicies.iter().flat_map(|i| i.to_le_bytes()));
+        views.push(format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}", offset, bin.len() - offset, ELEMENT_ARRAY_BUFFER));
+        accessors.push(format!("{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}", 2 * i + 1, UNSIGNED_INT, poly.indicies.len()));
+
+        let mut name = String::new();
+        json_string(&mut name, &definition.map_or_else(|| format!("{:02x}{:02x}{:02x}", r, g, b), |def| def.name.clone()));
+
+        materials.push(format!(
+            "{{\"name\":{},\"doubleSided\":true,\"pbrMetallicRoughness\":{{\"baseColorFactor\":[{},{},{},1],\"metallicFactor\":0,\"roughnessFactor\":1}},\"extensions\":{{\"KHR_materials_unlit\":{{}}}}}}",
+            name, linear(r), linear(g), linear(b),
+        ));
+        meshes.push(format!("{{\"name\":{},\"primitives\":[{{\"attributes\":{{\"POSITION\":{}}},\"indices\":{},\"material\":{}}}]}}", name, 2 * i, 2 * i + 1, i));
+
+        let mut extras = format!("\"color\":\"#{:02x}{:02x}{:02x}\"", r, g, b);
+        if let Some(def) = definition {
+            let _ = write!(extras, ",\"id\":{}", def.id);
+        }
+        if let Some(terrain) = definition.map(|def| def.terrain).or(poly.terrain) {
+            let _ = write!(extras, ",\"terrain\":\"{}\"", terrain_name(terrain));
+        }
+        nodes.push(format!("{{\"name\":{},\"mesh\":{},\"extras\":{{{}}}}}", name, i, extras));
+    }
+
+    let scene_nodes: Vec<String> = (0..nodes.len()).map(|i| i.to_string()).collect();
+    let json = format!(
+        "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"bmpoly\"}},\"extensionsUsed\":[\"KHR_materials_unlit\"],\"scene\":0,\"scenes\":[{{\"nodes\":[{}]}}],\
+        \"nodes\":[{}],\"meshes\":[{}],\"materials\":[{}],\"accessors\":[{}],\"bufferViews\":[{}]",
+        scene_nodes.join(","), nodes.join(","), meshes.join(","), materials.join(","), accessors.join(","), views.join(","),
+    );
+    (json, bin)
+}
+
+// Buffers can't be empty, so a map without triangles has none
+fn finish(mut json: String, byte_length: usize, uri: Option<&str>) -> String {
+    json.push_str(",\"buffers\":[");
+    if byte_length > 0 {
+        let _ = write!(json, "{{\"byteLength\":{}", byte_length);
+        if let Some(uri) = uri {
+            json.push_str(",\"uri\":");
+            json_string(&mut json, uri);
+        }
+        json.push('}');
+    }
+    json.push_str("]}");
+    json
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for k in 0..4 {
+            if k <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * k) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A self-contained glTF 2.0 document, with the buffer embedded as a data URI.
+///
+/// Every polygon becomes a node with its own mesh, named after its province, and an unlit material of its RGB value.
+/// The node's `extras` hold the color as `"#rrggbb"`, and the province `id` and `terrain` when known
+pub fn to_gltf(polygons: &[Polygon], definitions: &Definitions) -> String {
+    let (json, bin) = build(polygons, definitions);
+    let uri = format!("data:application/octet-stream;base64,{}", base64(&bin));
+    finish(json, bin.len(), Some(&uri))
+}
+
+/// The same document as [`to_gltf`], as a binary `.glb`
+pub fn write_glb(mut writer: impl io::Write, polygons: &[Polygon], definitions: &Definitions) -> io::Result<()> {
+    let (json, bin) = build(polygons, definitions);
+    let mut json = finish(json, bin.len(), None).into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let mut bin = bin;
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let length = 12 + 8 + json.len() + if bin.is_empty() { 0 } else { 8 + bin.len() };
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(length as u32).to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json)?;
+    if !bin.is_empty() {
+        writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+        writer.write_all(b"BIN\0")?;
+        writer.write_all(&bin)?;
+    }
+    Ok(())
+}
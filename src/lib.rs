@@ -1,12 +1,39 @@
+#[cfg(feature = "bevy")]
 use bevy::{asset::Handle, sprite::ColorMaterial};
 
 pub mod polygon;
+pub mod error;
+pub mod topology;
+mod geometry;
+mod simplify;
+mod smooth;
+pub mod adjacency;
 pub mod eu4;
+#[cfg(feature = "bevy")]
 pub mod province;
+#[cfg(feature = "bevy")]
 pub mod border_segment;
+#[cfg(feature = "bevy")]
+pub mod loader;
+pub mod spatial;
+pub mod lookup;
+pub mod source;
+pub mod bitmap;
+pub mod geojson;
+pub mod svg;
+pub mod gltf;
+pub mod obj;
+pub mod cache;
 
+pub use error::Error;
+
+#[cfg(feature = "bevy")]
 pub const LAND_MATERIAL_HANDLE: Handle<ColorMaterial> = Handle::weak_from_u128(0xf00_4befa6c0e7f11d40d8931715303ac);
+#[cfg(feature = "bevy")]
 pub const BORDER_MATERIAL_HANDLE: Handle<ColorMaterial> = Handle::weak_from_u128(0xf02_4befa6c0e7f11d40d8931715303ac);
+#[cfg(feature = "bevy")]
 pub const SELECTED_BORDER_MATERIAL_HANDLE: Handle<ColorMaterial> = Handle::weak_from_u128(0xf03_4befa6c0e7f11d40d8931715303ac);
+#[cfg(feature = "bevy")]
 pub const SEA_MATERIAL_HANDLE: Handle<ColorMaterial> = Handle::weak_from_u128(0xf04_4befa6c0e7f11d40d8931715303ac);
+#[cfg(feature = "bevy")]
 pub const SELECTED_PROV_MATERIAL_HANDLE: Handle<ColorMaterial> = Handle::weak_from_u128(0xf05_4befa6c0e7f11d40d8931715303ac);
\ No newline at end of file
@@ -0,0 +1,219 @@
+use std::{fmt, fs, io, path::PathBuf, time::{Instant, SystemTime}};
+
+use bevy::{app::{App, Plugin, Update}, asset::{io::Reader, Asset, AssetApp, AssetLoader, AssetServer, Assets, AsyncReadExt, Handle, LoadContext}, ecs::system::{Res, ResMut, Resource}, log::{info, warn}, reflect::TypePath, render::mesh::Mesh, time::{Time, Timer, TimerMode}};
+
+use crate::{adjacency::{load_adjacency, AdjacencyGraph}, bitmap::{self, BitmapError}, cache::{content_hash, load_cache, save_cache, MapCache}, eu4::{color_polys_with, Definitions}, lookup::ProvinceLookup, polygon::{try_load_polygons, Polygon}, source::PixelSource, Error};
+
+const DEFINITION_FILES: [&str; 3] = ["colors.txt", "seas.txt", "lakes.txt"];
+
+/// Registers [`ProvinceMapLoader`], so province bitmaps can be loaded with `asset_server.load("map.bmp")`.
+///
+/// Loaded maps are reloaded when the definition files change. With Bevy's `file_watcher` feature, changes to the bitmap itself reload it too
+#[derive(Debug, Clone, Default)]
+pub struct ProvinceMapPlugin {
+    /// Where traced maps are cached between runs, relative to the working directory. Nothing is cached when `None`, the default
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Plugin for ProvinceMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ProvinceMapAsset>();
+        app.register_asset_loader(ProvinceMapLoader { cache_dir: self.cache_dir.clone() });
+        app.insert_resource(DefinitionsWatcher {
+            modified: definitions_modified(),
+            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+        });
+        app.add_systems(Update, watch_definitions);
+    }
+}
+
+/// Last seen modification times of the definition files. They live outside the asset folder, so Bevy doesn't watch them
+#[derive(Resource)]
+struct DefinitionsWatcher {
+    modified: Vec<Option<SystemTime>>,
+    timer: Timer,
+}
+
+fn definitions_modified() -> Vec<Option<SystemTime>> {
+    DEFINITION_FILES.iter().map(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok()).collect()
+}
+
+fn watch_definitions(
+    time: Res<Time>,
+    mut watcher: ResMut<DefinitionsWatcher>,
+    maps: Res<Assets<ProvinceMapAsset>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let modified = definitions_modified();
+    if modified == watcher.modified {
+        return;
+    }
+    watcher.modified = modified;
+
+    for id in maps.ids() {
+        if let Some(path) = asset_server.get_path(id) {
+            asset_server.reload(path);
+        }
+    }
+}
+
+/// A traced and colored province bitmap, with a mesh for every polygon
+#[derive(Asset, TypePath)]
+pub struct ProvinceMapAsset {
+    pub width: u32,
+    pub height: u32,
+    pub polygons: Vec<Polygon>,
+    /// Mesh of each polygon, in the same order
+    pub meshes: Vec<Handle<Mesh>>,
+    /// Finds the province under a point, such as a click
+    pub lookup: ProvinceLookup,
+    pub adjacency: AdjacencyGraph,
+}
+
+/// Traces `.bmp` province maps on the asset loading threads, and `.png` and `.tga` ones with the `image` feature.
+/// Indexed bitmaps are traced on their palette indices.
+/// Colors come from colors.txt, seas.txt and lakes.txt in the working directory
+#[derive(Debug, Clone, Default)]
+pub struct ProvinceMapLoader {
+    /// When set, the result is cached here and only traced again when the bitmap or the definition files change
+    pub cache_dir: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ProvinceMapLoaderError {
+    Io(io::Error),
+    Bitmap(BitmapError),
+    #[cfg(feature = "image")]
+    Image(image::ImageError),
+    Polygons(Error),
+}
+
+impl fmt::Display for ProvinceMapLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProvinceMapLoaderError::Io(err) => write!(f, "failed to read province map: {}", err),
+            ProvinceMapLoaderError::Bitmap(err) => write!(f, "failed to decode province map: {}", err),
+            #[cfg(feature = "image")]
+            ProvinceMapLoaderError::Image(err) => write!(f, "failed to decode province map: {}", err),
+            ProvinceMapLoaderError::Polygons(err) => write!(f, "failed to trace province map: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProvinceMapLoaderError {}
+
+impl From<io::Error> for ProvinceMapLoaderError {
+    fn from(err: io::Error) -> Self {
+        ProvinceMapLoaderError::Io(err)
+    }
+}
+
+impl From<BitmapError> for ProvinceMapLoaderError {
+    fn from(err: BitmapError) -> Self {
+        ProvinceMapLoaderError::Bitmap(err)
+    }
+}
+
+#[cfg(feature = "image")]
+impl From<image::ImageError> for ProvinceMapLoaderError {
+    fn from(err: image::ImageError) -> Self {
+        ProvinceMapLoaderError::Image(err)
+    }
+}
+
+impl From<Error> for ProvinceMapLoaderError {
+    fn from(err: Error) -> Self {
+        ProvinceMapLoaderError::Polygons(err)
+    }
+}
+
+/// Decodes a province map by its file extension, the way [`ProvinceMapLoader`] does.
+/// `png` and `tga` need the `image` feature, and anything else is read as a BMP
+pub fn decode_map(bytes: &[u8], extension: Option<&str>) -> Result<Box<dyn PixelSource + Send>, ProvinceMapLoaderError> {
+    match extension {
+        #[cfg(feature = "image")]
+        Some(ext @ ("png" | "tga")) => {
+            let format = image::ImageFormat::from_extension(ext).unwrap();
+            Ok(Box::new(image::load_from_memory_with_format(bytes, format)?.to_rgba8()))
+        },
+        _ => Ok(Box::new(bitmap::from_bytes(bytes)?)),
+    }
+}
+
+/// Label of the mesh sub-asset for a color. Stable across reloads
+pub fn mesh_label((r, g, b): (u8, u8, u8)) -> String {
+    format!("mesh_{:02x}{:02x}{:02x}", r, g, b)
+}
+
+impl AssetLoader for ProvinceMapLoader {
+    type Asset = ProvinceMapAsset;
+    type Settings = ();
+    type Error = ProvinceMapLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<ProvinceMapAsset, ProvinceMapLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let img = decode_map(&bytes, load_context.path().extension().and_then(|ext| ext.to_str()))?;
+        let img = &*img;
+        let (width, height) = (img.width(), img.height());
+        let definitions = Definitions::load(".")?;
+        let lookup = ProvinceLookup::with_definitions(&img, &definitions);
+
+        let file_name = load_context.path().file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let cache = match &self.cache_dir {
+            Some(dir) => Some((dir, dir.join(format!("{}.cache", file_name)), content_hash(&bytes, ".")?)),
+            None => None,
+        };
+        let cached = cache.as_ref().and_then(|(_, path, hash)| match load_cache(path, *hash) {
+            Ok(Some(cached)) => {
+                info!("Loaded {} from {}", file_name, path.display());
+                Some(cached)
+            },
+            Ok(None) => None,
+            Err(err) => {
+                warn!("Ignoring {}: {}", path.display(), err);
+                None
+            },
+        });
+
+        let MapCache { polygons, adjacency } = match cached {
+            Some(cached) => cached,
+            None => {
+                // The meshes keep the diagonals the tracer cuts across staircases, adjacency needs the shared borders of the topology
+                let before = Instant::now();
+                let mut polygons = try_load_polygons(img)?;
+                color_polys_with(&mut polygons, &definitions);
+                let traced = MapCache { polygons, adjacency: load_adjacency(img, &definitions)? };
+                info!("Traced {} ({}x{}) in {}ms", file_name, width, height, before.elapsed().as_millis());
+                if let Some((dir, path, hash)) = &cache {
+                    if let Err(err) = fs::create_dir_all(dir).and_then(|_| save_cache(path, *hash, &traced)) {
+                        warn!("Failed to write {}: {}", path.display(), err);
+                    }
+                }
+                traced
+            },
+        };
+
+        let meshes = polygons.iter()
+            .map(|poly| load_context.add_labeled_asset(mesh_label(poly.source_color), poly.mesh()))
+            .collect();
+
+        Ok(ProvinceMapAsset { width, height, polygons, meshes, lookup, adjacency })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        #[cfg(feature = "image")]
+        return &["bmp", "png", "tga"];
+        #[cfg(not(feature = "image"))]
+        return &["bmp"];
+    }
+}
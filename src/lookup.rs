@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use crate::{eu4::Definitions, polygon::{Affine, LoadOptions}, source::PixelSource};
+
+const VOID: u32 = u32::MAX;
+
+/// Which region every pixel of the source bitmap belongs to, for finding the province under a point without any meshes.
+///
+/// World positions are in the coordinates of the traced polygons: pixel centers at whole numbers, with y pointing up,
+/// unless the polygons were loaded with other [`LoadOptions`]
+#[derive(Debug, Clone, Default)]
+pub struct ProvinceLookup {
+    width: usize,
+    height: usize,
+    /// Index into `colors` of every pixel, row by row from the bottom. `VOID` for void pixels
+    regions: Vec<u32>,
+    colors: Vec<(u8, u8, u8)>,
+    ids: Vec<Option<u32>>,
+    /// From pixel centers with y pointing up to world positions
+    to_world: Affine,
+}
+
+impl ProvinceLookup {
+    pub fn new(img: &impl PixelSource) -> Self {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut indices: HashMap<(u8, u8, u8), u32> = HashMap::new();
+        let mut colors = Vec::new();
+        let mut regions = vec![0; width * height];
+
+        for y in 0..height {
+            let act_y = height - y - 1;
+            for x in 0..width {
+                if img.is_void(x as u32, y as u32) {
+                    regions[act_y * width + x] = VOID;
+                    continue;
+                }
+                let color = img.color(x as u32, y as u32);
+                let index = *indices.entry(color).or_insert_with(|| {
+                    colors.push(color);
+                    colors.len() as u32 - 1
+                });
+                regions[act_y * width + x] = index;
+            }
+        }
+
+        let ids = vec![None; colors.len()];
+        ProvinceLookup { width, height, regions, colors, ids, to_world: Affine::default() }
+    }
+
+    /// Also resolves province IDs from colors.txt, by the RGB value of each traced color
+    pub fn with_definitions(img: &impl PixelSource, definitions: &Definitions) -> Self {
+        let mut lookup = Self::new(img);
+        lookup.ids = lookup.colors.iter().map(|color| definitions.get(img.rgb(*color)).map(|def| def.id)).collect();
+        lookup
+    }
+
+    /// Takes world positions in the coordinates of polygons loaded with `options`
+    pub fn with_options(mut self, options: &LoadOptions) -> Self {
+        self.to_world = options.world_transform(self.height as u32);
+        self
+    }
+
+    fn region(&self, world_pos: (f32, f32)) -> Option<usize> {
+        let (width, height) = (self.width, self.height);
+        let (x, y) = self.to_world.invert(world_pos);
+        let (x, y) = ((x + 0.5).floor(), (y + 0.5).floor());
+        if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+            return None;
+        }
+        Some(y as usize * width + x as usize)
+    }
+
+    /// Color of the region at a world position, `None` outside the map and on void pixels
+    pub fn lookup(&self, world_pos: (f32, f32)) -> Option<(u8, u8, u8)> {
+        self.index(world_pos).map(|index| self.colors[index])
+    }
+
+    /// Province ID at a world position. `None` outside the map, on void pixels, for colors missing from colors.txt,
+    /// and always when built without definitions
+    pub fn lookup_id(&self, world_pos: (f32, f32)) -> Option<u32> {
+        self.index(world_pos).and_then(|index| self.ids[index])
+    }
+
+    fn index(&self, world_pos: (f32, f32)) -> Option<usize> {
+        self.region(world_pos)
+            .map(|i| self.regions[i])
+            .filter(|index| *index != VOID)
+            .map(|index| index as usize)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
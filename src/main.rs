@@ -1,18 +1,15 @@
 use std::collections::HashMap;
 
-use bevy::render::render_asset::RenderAssetUsages;
 use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
 use bevy::window::{PresentMode, PrimaryWindow};
-use bevy_mod_raycast::immediate::{Raycast, RaycastSettings, RaycastVisibility};
 use bevy_pancam::{PanCam, PanCamPlugin};
 use bevy_polyline2d::{Align, Polyline2dBundle, Polyline2dPlugin};
-use bmpoly::polygon::load_polygons;
-use bmpoly::eu4::color_polys;
+use bmpoly::loader::{ProvinceMapAsset, ProvinceMapPlugin};
 use bevy_debug_text_overlay::{screen_print, OverlayPlugin};
 
 use bevy::prelude::*;
-use bevy::render::mesh::{self, PrimitiveTopology};
 use bmpoly::*;
+use bmpoly::polygon::{diff_polygons, Polygon};
 
 const FILL: Visibility = Visibility::Visible;
 const OUTLINE: Visibility = Visibility::Visible;
@@ -37,6 +34,7 @@ struct RenderedPoly {
     _mesh: Handle<Mesh>,
     entity_id: Entity,
     border_ids: Vec<Entity>,
+    vertex_ids: Vec<Entity>,
 }
 
 impl RenderedPoly {
@@ -45,12 +43,14 @@ impl RenderedPoly {
         mesh: Handle<Mesh>,
         entity_id: Entity,
         border_ids: Vec<Entity>,
+        vertex_ids: Vec<Entity>,
     ) -> Self {
         Self {
             base_mat,
             _mesh: mesh,
             entity_id,
             border_ids,
+            vertex_ids,
         }
     }
 }
@@ -58,6 +58,9 @@ impl RenderedPoly {
 #[derive(Resource)]
 struct PolyMap {
     map: HashMap<Entity, RenderedPoly>,
+    by_color: HashMap<(u8, u8, u8), Entity>,
+    /// The polygons currently spawned, to diff against when the map is reloaded
+    polygons: Vec<Polygon>,
 }
 
 impl PolyMap {
@@ -76,6 +79,8 @@ fn main() {
         .insert_resource(Msaa::Sample4)
         .insert_resource(PolyMap {
             map: HashMap::new(),
+            by_color: HashMap::new(),
+            polygons: Vec::new(),
         })
         .insert_resource(Selected {
             rp: None,
@@ -87,115 +92,24 @@ fn main() {
             }),
             ..Default::default()
         }))
-        .add_plugins((PanCamPlugin, Polyline2dPlugin, MaterialPlugin))
+        .add_plugins((PanCamPlugin, Polyline2dPlugin, MaterialPlugin, ProvinceMapPlugin { cache_dir: Some("cache".into()) }))
         .add_plugins(OverlayPlugin { font_size: 23.0, ..default() })
         .add_systems(Update, screen_print_text)
 
         .add_systems(Startup, setup)
-        .add_systems(Update, click_system)
+        .add_systems(Update, (spawn_map, click_system))
         .run();
 }
 
-#[derive(Component)]
-struct PolyMesh;
+#[derive(Resource)]
+struct MapHandle(Handle<ProvinceMapAsset>);
 
 fn setup (
     mut commands: Commands, 
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut poly_map: ResMut<PolyMap>,
+    asset_server: Res<AssetServer>,
 ) {
-    let before = std::time::Instant::now();
-    let img = bmp::open("assets/old_world.bmp").unwrap();
-    let (width, height) = (img.get_width(), img.get_height());
-    let mut polys = load_polygons(img);
-
-    let mut total_entities = 0;
-
-    color_polys(&mut polys);
-
-    let mut vertices = 0;
+    commands.insert_resource(MapHandle(asset_server.load("old_world.bmp")));
 
-    let before_meshes = std::time::Instant::now();
-    for poly in polys.into_iter() {
-        let base_mat = poly.mat_handle.clone();
-        
-        let (id, mesh) = {
-            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default()).with_inserted_attribute(
-                Mesh::ATTRIBUTE_POSITION,
-                poly.vertices.clone(),
-            ).with_inserted_indices(mesh::Indices::U32(poly.indicies.clone()));
-            vertices += poly.vertices.len();
-            mesh.duplicate_vertices();
-            mesh.compute_flat_normals();
-
-            let mesh = meshes.add(Mesh::from(mesh));
-            let id = commands.spawn((
-                MaterialMesh2dBundle {
-                    mesh: mesh.clone().into(),
-                    material: base_mat.clone(),
-                    visibility: FILL,
-                    ..default()
-                },
-                PolyMesh {},
-            )).id();
-            total_entities += 1;
-
-            (id, mesh)
-        };
-
-        let mut border_ids = Vec::new();
-
-        for border in poly.border_vertices.iter() {
-            border_ids.push({
-                let polyline = bevy_polyline2d::Polyline2d {
-                    path: border.clone(),
-                    closed: true,
-                    width: 0.1,
-                    line_placement: Align::Left,
-                };
-    
-                total_entities += 1;
-                commands.spawn(Polyline2dBundle {
-                    polyline,
-                    material: BORDER_MATERIAL_HANDLE,
-                    transform: Transform::from_translation(Vec3::new(0.0, 0.0, 1.0)),
-                    visibility: OUTLINE,
-                    ..default()
-                }).id()
-            });
-        }
-
-        if VERTICES {
-            for border in poly.border_vertices.iter() {
-                for vertex in border {
-                    commands.spawn(SpriteBundle {
-                        sprite: Sprite {
-                            color: bevy::color::palettes::basic::BLUE.into(),
-                            custom_size: Some(Vec2::new(0.3, 0.3)),
-                            ..default()
-                        },
-                        transform: Transform::from_translation(Vec3::new(vertex[0], vertex[1], 2.)),
-                        ..default()
-                    });
-                    total_entities += 1;
-                }
-            }
-        }
-
-        poly_map.map.insert(id, RenderedPoly::new(
-            base_mat,
-            mesh,
-            id,
-            border_ids,
-        ));
-    }
-
-    println!("Created meshes in {}ms", before_meshes.elapsed().as_millis());
-
-    println!("Total time: {}ms", before.elapsed().as_millis());
-    println!("Total vertices: {}", vertices);
-    println!("Total entities: {}", total_entities);
-    
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
             illuminance: 10000.,
@@ -205,7 +119,7 @@ fn setup (
     });
 
     commands.spawn(Camera2dBundle {
-        transform: Transform::from_translation(Vec3::new(width as f32 / 1.9, height as f32 / 1.3, 1000.)),
+        transform: Transform::from_translation(Vec3::new(0., 0., 1000.)),
         projection: OrthographicProjection {
             scale: 0.5,
             ..default()
@@ -219,17 +133,164 @@ fn setup (
     });
 }
 
+/// Spawns the fill, borders and vertex markers of one polygon. Returns the number of entities spawned
+fn spawn_polygon(
+    commands: &mut Commands,
+    poly: &Polygon,
+    mesh: Handle<Mesh>,
+    poly_map: &mut PolyMap,
+) -> usize {
+    let base_mat = poly.material();
+    let mut total_entities = 0;
+
+    let id = commands.spawn(MaterialMesh2dBundle {
+        mesh: mesh.clone().into(),
+        material: base_mat.clone(),
+        visibility: FILL,
+        ..default()
+    }).id();
+    total_entities += 1;
+
+    let mut border_ids = Vec::new();
+
+    for border in poly.border_vertices.iter() {
+        border_ids.push({
+            let polyline = bevy_polyline2d::Polyline2d {
+                path: border.clone(),
+                closed: true,
+                width: 0.1,
+                line_placement: Align::Left,
+            };
+
+            total_entities += 1;
+            commands.spawn(Polyline2dBundle {
+                polyline,
+                material: BORDER_MATERIAL_HANDLE,
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 1.0)),
+                visibility: OUTLINE,
+                ..default()
+            }).id()
+        });
+    }
+
+    let mut vertex_ids = Vec::new();
+    if VERTICES {
+        for border in poly.border_vertices.iter() {
+            for vertex in border {
+                vertex_ids.push(commands.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: bevy::color::palettes::basic::BLUE.into(),
+                        custom_size: Some(Vec2::new(0.3, 0.3)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(Vec3::new(vertex[0], vertex[1], 2.)),
+                    ..default()
+                }).id());
+                total_entities += 1;
+            }
+        }
+    }
+
+    poly_map.by_color.insert(poly.source_color, id);
+    poly_map.map.insert(id, RenderedPoly::new(
+        base_mat,
+        mesh,
+        id,
+        border_ids,
+        vertex_ids,
+    ));
+
+    total_entities
+}
+
+fn despawn_polygon(
+    commands: &mut Commands,
+    color: (u8, u8, u8),
+    poly_map: &mut PolyMap,
+    selected: &mut Selected,
+) {
+    let Some(rp) = poly_map.by_color.remove(&color).and_then(|id| poly_map.map.remove(&id)) else {
+        return;
+    };
+    if selected.rp.as_ref().is_some_and(|s| s.entity_id == rp.entity_id) {
+        selected.rp = None;
+    }
+    commands.entity(rp.entity_id).despawn();
+    for id in rp.border_ids.iter().chain(&rp.vertex_ids) {
+        commands.entity(*id).despawn();
+    }
+}
+
+fn spawn_map(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<ProvinceMapAsset>>,
+    maps: Res<Assets<ProvinceMapAsset>>,
+    map_handle: Res<MapHandle>,
+    mut poly_map: ResMut<PolyMap>,
+    mut selected: ResMut<Selected>,
+    mut q_camera: Query<&mut Transform, With<Camera>>,
+) {
+    for event in events.read() {
+        if !event.is_loaded_with_dependencies(&map_handle.0) && !event.is_modified(&map_handle.0) {
+            continue;
+        }
+        let Some(map) = maps.get(&map_handle.0) else {
+            continue;
+        };
+
+        // Only respawn the polygons that changed since the last load
+        let first_load = poly_map.polygons.is_empty();
+        let diff = diff_polygons(&poly_map.polygons, &map.polygons);
+        if diff.is_empty() {
+            continue;
+        }
+
+        let mut total_entities = 0;
+        let mut vertices = 0;
+
+        let before_meshes = std::time::Instant::now();
+        for color in diff.removed.iter().chain(diff.changed.iter().map(|poly| &poly.source_color)) {
+            despawn_polygon(&mut commands, *color, &mut poly_map, &mut selected);
+        }
+
+        let meshes: HashMap<(u8, u8, u8), &Handle<Mesh>> = map.polygons.iter()
+            .map(|poly| poly.source_color)
+            .zip(&map.meshes)
+            .collect();
+        for poly in diff.changed.iter().chain(&diff.added) {
+            vertices += poly.vertices.len();
+            total_entities += spawn_polygon(&mut commands, poly, meshes[&poly.source_color].clone(), &mut poly_map);
+        }
+        poly_map.polygons = map.polygons.clone();
+
+        println!("Created meshes in {}ms", before_meshes.elapsed().as_millis());
+        println!(
+            "Added {}, removed {}, changed {} polygons",
+            diff.added.len(), diff.removed.len(), diff.changed.len()
+        );
+
+        println!("Total vertices: {}", vertices);
+        println!("Total entities: {}", total_entities);
+
+        if first_load {
+            for mut transform in q_camera.iter_mut() {
+                transform.translation = Vec3::new(map.width as f32 / 1.9, map.height as f32 / 1.3, 1000.);
+            }
+        }
+    }
+}
+
 fn click_system(
     buttons: Res<ButtonInput<MouseButton>>,
     q_window: Query<&Window, With<PrimaryWindow>>,
     // query to get camera transform
     q_camera: Query<(&Camera, &GlobalTransform)>,
-    q_poly_mesh: Query<Has<PolyMesh>>,
+    maps: Res<Assets<ProvinceMapAsset>>,
+    map_handle: Res<MapHandle>,
 
     mut materials: ResMut<Assets<ColorMaterial>>,
     poly_map: ResMut<PolyMap>,
     mut selected: ResMut<Selected>,
-    mut raycast: Raycast,
     mut commands: Commands,
 ) {
     // If mouse button clicked
@@ -262,20 +323,20 @@ fn click_system(
 
     // check if the cursor is inside the window and get its position
     // then, ask bevy to convert into world coordinates, and truncate to discard Z
-    let loc = window.cursor_position()
+    let Some(loc) = window.cursor_position()
         .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-        .map(|ray| ray.origin.truncate()).unwrap();
-
-    let ray = Ray3d::new(Vec3::new(loc.x, loc.y, 100.), Vec3::new(0., 0., -1.));
-    let hits = raycast.cast_ray(ray, 
-        &RaycastSettings::default()
-            .with_visibility(RaycastVisibility::MustBeVisibleAndInView)
-            .always_early_exit()
-            .with_filter(&|e| q_poly_mesh.contains(e))
-        );
+        .map(|ray| ray.origin.truncate()) else {
+        return;
+    };
+
+    // Find the province under the cursor in the source bitmap
+    let Some(map) = maps.get(&map_handle.0) else {
+        return;
+    };
+    let hit = map.lookup.lookup((loc.x, loc.y)).and_then(|color| poly_map.by_color.get(&color).copied());
 
     // Select
-    if let Some(hit) = hits.get(0).map(|h| h.0) {
+    if let Some(hit) = hit {
         if let Some(rp) = poly_map.get(&hit) {
             let mut entity = commands.entity(hit);
             let base_color: LinearRgba = materials.get(&rp.base_mat).unwrap().color.into();
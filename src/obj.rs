@@ -0,0 +1,34 @@
+use std::{fmt::Write as _, io};
+
+use crate::{eu4::Definitions, polygon::Polygon};
+
+/// A Wavefront OBJ file with an object per polygon, named `province_<id>`, or `color_rrggbb` for colors without a definition.
+///
+/// The RGB value of each region is written as a vertex color after each position, which Blender and most other tools read
+pub fn to_obj(polygons: &[Polygon], definitions: &Definitions) -> String {
+    let mut out = String::from("# bmpoly\n");
+    // OBJ indices count from 1 across the whole file
+    let mut first = 1;
+
+    for poly in polygons {
+        let (r, g, b) = poly.rgb;
+        match definitions.get(poly.rgb) {
+            Some(def) => { let _ = writeln!(out, "o province_{}", def.id); },
+            None => { let _ = writeln!(out, "o color_{:02x}{:02x}{:02x}", r, g, b); },
+        }
+
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+        for v in &poly.vertices {
+            let _ = writeln!(out, "v {} {} {} {} {} {}", v[0], v[1], v[2], r, g, b);
+        }
+        for tri in poly.indicies.chunks_exact(3) {
+            let _ = writeln!(out, "f {} {} {}", first + tri[0], first + tri[1], first + tri[2]);
+        }
+        first += poly.vertices.len() as u32;
+    }
+    out
+}
+
+pub fn write_obj(mut writer: impl io::Write, polygons: &[Polygon], definitions: &Definitions) -> io::Result<()> {
+    writer.write_all(to_obj(polygons, definitions).as_bytes())
+}
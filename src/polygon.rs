@@ -1,20 +1,69 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Polygon {
-    pub mat_handle: Handle<ColorMaterial>,
+    /// Set by [`color_polys`](crate::eu4::color_polys) from the definition files. Picks the material with the `bevy` feature
+    pub terrain: Option<TerrainType>,
     pub source_color: (u8, u8, u8),
+    /// The RGB value `source_color` stands for, see [`PixelSource::rgb`]. Definitions are keyed by this one
+    pub rgb: (u8, u8, u8),
     pub vertices: Vec<[f32; 3]>,
+    /// Every ring of the region: the outer ring of each part, followed by its holes
     pub border_vertices: Vec<Vec<[f32; 3]>>,
+    /// The arcs making up each ring in `border_vertices`, when built from a [`Topology`](crate::topology::Topology).
+    /// Empty for polygons traced by [`load_polygons`]
+    pub arc_rings: Vec<Vec<ArcRef>>,
     pub indicies: Vec<u32>,
+    /// Disjoint parts of the region, such as an island and its mainland, in the same order as their rings in `border_vertices`
+    pub parts: Vec<PolygonPart>,
+}
+
+/// One connected part of a region: an outer ring and the holes in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolygonPart {
+    /// Counter-clockwise for polygons built from a [`Topology`](crate::topology::Topology)
+    pub outer: Vec<[f32; 3]>,
+    pub holes: Vec<Vec<[f32; 3]>>,
+    /// Area of the outer ring minus its holes
+    pub area: f32,
+    pub bounds: BoundingBox,
+    /// The part's triangles in the polygon's `indicies`
+    pub indices: Range<usize>,
+}
+
+/// An axis aligned box, in the same coordinates as the vertices
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingBox {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl BoundingBox {
+    pub fn from_points(points: impl IntoIterator<Item = (f32, f32)>) -> Self {
+        points.into_iter().fold(
+            BoundingBox { min: (f32::MAX, f32::MAX), max: (f32::MIN, f32::MIN) },
+            |b, p| BoundingBox { min: (b.min.0.min(p.0), b.min.1.min(p.1)), max: (b.max.0.max(p.0), b.max.1.max(p.1)) },
+        )
+    }
+
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min.0 <= other.max.0 && other.min.0 <= self.max.0 && self.min.1 <= other.max.1 && other.min.1 <= self.max.1
+    }
 }
 
 impl Polygon {
     fn new(color: (u8, u8, u8)) -> Self {
         Self {
-            mat_handle: Handle::default(),
+            terrain: None,
             source_color: color,
+            rgb: color,
             vertices: Vec::new(),
             border_vertices: Vec::new(),
+            arc_rings: Vec::new(),
             indicies: Vec::new(),
+            parts: Vec::new(),
         }
     }
 
@@ -25,7 +74,29 @@ impl Polygon {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg(feature = "bevy")]
+impl Polygon {
+    /// The sea material for seas and lakes, the land material for land, and the default material before coloring
+    pub fn material(&self) -> Handle<ColorMaterial> {
+        match self.terrain {
+            Some(TerrainType::Sea | TerrainType::Lake) => SEA_MATERIAL_HANDLE,
+            Some(TerrainType::Land) => LAND_MATERIAL_HANDLE,
+            None => Handle::default(),
+        }
+    }
+
+    /// A flat shaded triangle mesh of the polygon
+    pub fn mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices.clone())
+            .with_inserted_indices(Indices::U32(self.indicies.clone()));
+        mesh.duplicate_vertices();
+        mesh.compute_flat_normals();
+        mesh
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 enum Direction {
     North = 0,
     South = 1,
@@ -33,38 +104,47 @@ enum Direction {
     West = 3,
 }
 
-use std::collections::{HashMap, VecDeque, HashSet};
+const DIRECTIONS: [Direction; 4] = [North, South, East, West];
+
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, ops::Range};
 
 use Direction::*;
-use bevy::{asset::Handle, sprite::ColorMaterial};
-use bmp::Image;
+#[cfg(feature = "bevy")]
+use bevy::{asset::Handle, render::{mesh::{Indices, Mesh, PrimitiveTopology}, render_asset::RenderAssetUsages}, sprite::ColorMaterial};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{eu4::TerrainType, geometry, source::PixelSource, spatial::SpatialGrid, topology::ArcRef, Error};
+#[cfg(feature = "bevy")]
+use crate::{LAND_MATERIAL_HANDLE, SEA_MATERIAL_HANDLE};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+// Field order matters: positions are ordered row by row, bottom to top, for deterministic tracing
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Position {
-    x: usize,
     y: usize,
+    x: usize,
     dir: Direction,
 }
 
 impl Position {
     // Corner vertex forward/right
-    fn corner_vertex(&self) -> (f32, f32) {
+    fn corner_vertex(&self, to_world: &Affine) -> (f32, f32) {
         let (x, y) = (self.x as f32, self.y as f32);
-        match self.dir {
+        to_world.apply(match self.dir {
             North => (x + 0.5, y + 0.5),
             South => (x - 0.5, y - 0.5),
             West => (x - 0.5, y + 0.5),
             East => (x + 0.5, y - 0.5),
-        }
+        })
     }
 
-    fn vertex(&self) -> (f32, f32) {
-        match self.dir {
+    fn vertex(&self, to_world: &Affine) -> (f32, f32) {
+        to_world.apply(match self.dir {
             North => (self.x as f32, self.y as f32 + 0.5),
             South => (self.x as f32, self.y as f32 - 0.5),
             West => (self.x as f32 - 0.5, self.y as f32),
             East => (self.x as f32 + 0.5, self.y as f32),
-        }
+        })
     }
 
     fn rotate_right(&self) -> Self {
@@ -95,6 +175,38 @@ impl Position {
     }
 }
 
+/// Scales and then moves points, separately on each axis
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct Affine {
+    /// Added before scaling. Pixel positions stay exact when shifted, so a window of the image gives the same vertices as the whole of it
+    shift: (f32, f32),
+    offset: (f32, f32),
+    factor: (f32, f32),
+}
+
+impl Affine {
+    const IDENTITY: Affine = Affine { shift: (0.0, 0.0), offset: (0.0, 0.0), factor: (1.0, 1.0) };
+
+    pub(crate) fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.offset.0 + self.factor.0 * (x + self.shift.0), self.offset.1 + self.factor.1 * (y + self.shift.1))
+    }
+
+    pub(crate) fn invert(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        ((x - self.offset.0) / self.factor.0 - self.shift.0, (y - self.offset.1) / self.factor.1 - self.shift.1)
+    }
+
+    // The same transform for points given relative to `by`
+    fn shifted(&self, by: (f32, f32)) -> Affine {
+        Affine { shift: (self.shift.0 + by.0, self.shift.1 + by.1), ..*self }
+    }
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Affine::IDENTITY
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum Turn {
     Left,
@@ -103,50 +215,93 @@ enum Turn {
     Corner,
 }
 
+/// Colors and border flags of every pixel, row by row from the bottom.
+/// Each pixel has a flag bit per direction, so the flags are ordered like positions
 #[derive(Debug, Clone)]
 struct BorderMap {
-    colors: Vec<Vec<(u8, u8, u8)>>,
-    borders: Vec<Vec<[bool; 4]>>,
-    border_set: HashSet<Position>,
+    width: usize,
+    height: usize,
+    colors: Vec<(u8, u8, u8)>,
+    flags: Vec<u64>,
+    /// A bit per pixel, set for void pixels
+    void: Vec<u64>,
+    /// No flag before this bit is set. Flags are only cleared while tracing, so the next start edge is never before it
+    cursor: usize,
+    /// Position of the map in the whole image, with y pointing up. Non-zero when only a window of the image is loaded
+    offset: (usize, usize),
+    image_height: usize,
+    /// From positions in the map to output vertices
+    to_world: Affine,
 }
 
 impl BorderMap {
     fn new(width: usize, height: usize) -> BorderMap {
         BorderMap {
-            colors: vec![vec![(0, 0, 0); height]; width],
-            borders: vec![vec![[false; 4]; height]; width],
-            border_set: HashSet::new(),
+            width,
+            height,
+            colors: vec![(0, 0, 0); width * height],
+            flags: vec![0; (width * height * 4).div_ceil(64)],
+            void: vec![0; (width * height).div_ceil(64)],
+            cursor: 0,
+            offset: (0, 0),
+            image_height: height,
+            to_world: Affine::IDENTITY,
         }
     }
 
+    fn bit(&self, pos: &Position) -> usize {
+        ((pos.y * self.width + pos.x) << 2) | pos.dir as usize
+    }
+
     fn get(&self, pos: &Position) -> bool {
-        self.borders[pos.x][pos.y][pos.dir as usize]
+        let bit = self.bit(pos);
+        self.flags[bit >> 6] & (1 << (bit & 63)) != 0
     }
 
-    fn get_clr(&self, pos: &Position) -> (u8, u8, u8) {
-        self.colors[pos.x][pos.y]
+    /// `None` for void pixels, so they never match a traced color
+    fn get_clr(&self, pos: &Position) -> Option<(u8, u8, u8)> {
+        let i = pos.y * self.width + pos.x;
+        if self.void[i >> 6] & (1 << (i & 63)) != 0 {
+            return None;
+        }
+        Some(self.colors[i])
     }
 
     fn remove(&mut self, pos: &Position) {
-        self.borders[pos.x][pos.y][pos.dir as usize] = false;
-        self.border_set.remove(pos);
+        let bit = self.bit(pos);
+        self.flags[bit >> 6] &= !(1 << (bit & 63));
     }
 
     fn insert_clr(&mut self, (x, y): (usize, usize), clr: (u8, u8, u8)) {
-        self.colors[x][y] = clr;
+        self.colors[y * self.width + x] = clr;
+    }
+
+    fn insert_void(&mut self, (x, y): (usize, usize)) {
+        let i = y * self.width + x;
+        self.void[i >> 6] |= 1 << (i & 63);
     }
 
     fn insert(&mut self, pos: &Position) {
-        self.borders[pos.x][pos.y][pos.dir as usize] = true;
-        self.border_set.insert(*pos);
+        let bit = self.bit(pos);
+        self.flags[bit >> 6] |= 1 << (bit & 63);
     }
-    
+
+    // The first border left, skipping 16 pixels at a time where there are none
     fn get_some_starting_point(&mut self) -> Option<Position> {
-        self.border_set.iter().next().copied()
+        let mut word = self.cursor >> 6;
+        let mut bits = self.flags.get(word)? & (u64::MAX << (self.cursor & 63));
+        while bits == 0 {
+            word += 1;
+            bits = *self.flags.get(word)?;
+        }
+
+        self.cursor = (word << 6) | bits.trailing_zeros() as usize;
+        let pixel = self.cursor >> 2;
+        Some(Position { y: pixel / self.width, x: pixel % self.width, dir: DIRECTIONS[self.cursor & 3] })
     }
-    
+
     fn pop_next_border(&mut self, pos: &Position) -> Option<(Position, (f32, f32), Option<(f32, f32)>, Turn)> {
-        let dims = (self.borders.len(), self.borders[0].len());
+        let dims = (self.width, self.height);
 
         // Check left turn
         {
@@ -173,13 +328,13 @@ impl BorderMap {
                 let corner = if is_corner {
                     //println!("{:?}: {:?} at {:?}", npos, self.get_clr(&npos), npos.corner_vertex());
 
-                    Some(npos.corner_vertex())
+                    Some(npos.corner_vertex(&self.to_world))
                 } else {
                     None
                 }; 
 
                 self.remove(&npos);
-                return Some((npos, npos.vertex(), corner, Turn::Left));
+                return Some((npos, npos.vertex(&self.to_world), corner, Turn::Left));
             }
         }
 
@@ -190,7 +345,7 @@ impl BorderMap {
                 let npos = npos.rotate_right();
                 if self.get(&npos) && self.get_clr(&npos) == self.get_clr(pos) {
                     self.remove(&npos);
-                    return Some((npos, npos.vertex(), None, Turn::Straight));
+                    return Some((npos, npos.vertex(&self.to_world), None, Turn::Straight));
                 }
             }
         }
@@ -206,7 +361,7 @@ impl BorderMap {
                     let corner = pos.rotate_left().move_fwd(dims).unwrap();
                     if self.get(&npos) && self.get_clr(&npos) == self.get_clr(pos) && self.get_clr(&pos) == self.get_clr(&corner) {
                         self.remove(&npos);
-                        return Some((npos, npos.vertex(), None, Turn::Right));
+                        return Some((npos, npos.vertex(&self.to_world), None, Turn::Right));
                     }
                 }
             }
@@ -251,7 +406,7 @@ impl BorderMap {
         //println!("Starting at {:?}", origin);
 
         let mut vertices: Vec<(f32, f32)> = Vec::new();
-        let color = self.get_clr(&origin);
+        let color = self.get_clr(&origin)?;
 
         //println!("Starting at {:?}", origin);
 
@@ -285,36 +440,62 @@ impl BorderMap {
 
         let is_hole = rights > lefts;
         //if is_hole { vertices.reverse() }
-        let dims = (self.borders.len(), self.borders[0].len());
-        return Some((RawPolygon { is_hole, verticies: vertices, point_inside: origin.move_fwd(dims), holes: Vec::new() }, color));
+        let dims = (self.width, self.height);
+        let (dx, dy) = self.offset;
+        let origin_px = ((origin.x + dx) as u32, (self.image_height - origin.y - dy - 1) as u32);
+        let point_inside = origin.move_fwd(dims).map(|pos| self.to_world.apply((pos.x as f32, pos.y as f32)));
+        let pixel = self.to_world.apply((origin.x as f32, origin.y as f32));
+        let bounds = BoundingBox::from_points(vertices.iter().copied());
+        let mut poly = RawPolygon { is_hole, verticies: vertices, bounds, point_inside, pixel, origin: origin_px, arcs: Vec::new(), holes: Vec::new() };
+        poly.rotate_to_lowest();
+        return Some((poly, color));
     }
 
-    fn load(img: Image) -> Self {
-        println!("Image dimensions: {}x{}", img.get_width(), img.get_height());
-
-        let (width, height) = (img.get_width(), img.get_height());
-
-        let mut borders = BorderMap::new(width as usize, height as usize);
+    #[cfg(any(test, not(feature = "parallel")))]
+    fn load(img: &impl PixelSource, options: &LoadOptions) -> Self {
+        let window = PixelRect { x: 0, y: 0, width: img.width(), height: img.height() };
+        Self::load_window(img, window, options, |_| true)
+    }
 
-        for (x, y) in img.coordinates() {
-            let act_y = height - y - 1;
-            let color = img.get_pixel(x, y);
-            borders.insert_clr((x as usize, act_y as usize), (color.r, color.g, color.b));
-            if x == 0 || img.get_pixel(x - 1, y) != color {
-                let pos = Position { x: x as usize, y: act_y as usize, dir: Direction::West };
-                borders.insert(&pos)
-            }
-            if x == width - 1 || img.get_pixel(x + 1, y) != color {
-                let pos = Position { x: x as usize, y: act_y as usize, dir: Direction::East };
-                borders.insert(&pos)
-            }
-            if y == 0 || img.get_pixel(x, y - 1) != color {
-                let pos = Position { x: x as usize, y: act_y as usize, dir: Direction::North };
-                borders.insert(&pos)
-            }
-            if y == height - 1 || img.get_pixel(x, y + 1) != color {
-                let pos = Position { x: x as usize, y: act_y as usize, dir: Direction::South };
-                borders.insert(&pos)
+    /// Loads the pixels inside `window`, only marking the borders of colors that pass `traced`.
+    /// Borders are found against the whole image, so the window edge is not a border. Void pixels are never traced, but border their neighbors
+    fn load_window(img: &impl PixelSource, window: PixelRect, options: &LoadOptions, traced: impl Fn(&(u8, u8, u8)) -> bool) -> Self {
+        let (width, height) = (img.width(), img.height());
+
+        let mut borders = BorderMap::new(window.width as usize, window.height as usize);
+        borders.offset = (window.x as usize, (height - window.y - window.height) as usize);
+        borders.image_height = height as usize;
+        borders.to_world = options.world_transform(height).shifted((borders.offset.0 as f32, borders.offset.1 as f32));
+
+        for y in window.y..window.y + window.height {
+            for x in window.x..window.x + window.width {
+                let (local_x, act_y) = ((x - window.x) as usize, (window.y + window.height - y - 1) as usize);
+                if img.is_void(x, y) {
+                    borders.insert_void((local_x, act_y));
+                    continue;
+                }
+                let color = img.color(x, y);
+                borders.insert_clr((local_x, act_y), color);
+                if !traced(&color) {
+                    continue;
+                }
+                let differs = |x, y| img.is_void(x, y) || img.color(x, y) != color;
+                if x == 0 || differs(x - 1, y) {
+                    let pos = Position { x: local_x, y: act_y, dir: Direction::West };
+                    borders.insert(&pos)
+                }
+                if x == width - 1 || differs(x + 1, y) {
+                    let pos = Position { x: local_x, y: act_y, dir: Direction::East };
+                    borders.insert(&pos)
+                }
+                if y == 0 || differs(x, y - 1) {
+                    let pos = Position { x: local_x, y: act_y, dir: Direction::North };
+                    borders.insert(&pos)
+                }
+                if y == height - 1 || differs(x, y + 1) {
+                    let pos = Position { x: local_x, y: act_y, dir: Direction::South };
+                    borders.insert(&pos)
+                }
             }
         }
 
@@ -323,30 +504,44 @@ impl BorderMap {
 }
 
 #[derive(Debug, Clone)]
-struct RawPolygon {
-    is_hole: bool,
-    verticies: Vec<(f32, f32)>,
-    point_inside: Option<Position>,
-    holes: Vec<RawPolygon>,
+pub(crate) struct RawPolygon {
+    pub(crate) is_hole: bool,
+    pub(crate) verticies: Vec<(f32, f32)>,
+    pub(crate) bounds: BoundingBox,
+    /// Center of a pixel just outside the ring, which lies inside it when the ring is a hole
+    pub(crate) point_inside: Option<(f32, f32)>,
+    /// Center of the pixel of the ring's own color that `point_inside` is next to
+    pub(crate) pixel: (f32, f32),
+    /// Pixel the ring was traced from, in image coordinates. Used for error reporting
+    pub(crate) origin: (u32, u32),
+    pub(crate) arcs: Vec<ArcRef>,
+    pub(crate) holes: Vec<RawPolygon>,
 }
 
 impl RawPolygon {
-    fn is_inside(&self, (x, y): (f32, f32)) -> bool {
-        let mut c = false;
-        for i in 0..self.verticies.len() {
-            let j = (i + 1) % self.verticies.len();
-            if ((self.verticies[i].1 > y) != (self.verticies[j].1 > y)) && (x < (self.verticies[j].0 - self.verticies[i].0) * (y - self.verticies[i].1) / (self.verticies[j].1 - self.verticies[i].1) + self.verticies[i].0) {
-                c = !c;
-            }
-        }
-        return c;
+    fn is_inside(&self, point: (f32, f32)) -> bool {
+        self.bounds.contains(point) && geometry::contains([&self.verticies], point)
+    }
+
+    // A point strictly inside the ring, away from its border
+    fn sample_point(&self) -> Option<(f32, f32)> {
+        if self.is_hole { self.point_inside } else { Some(self.pixel) }
+    }
+
+    /// Rotates the ring so it starts at its lowest vertex, the leftmost one on ties
+    pub(crate) fn rotate_to_lowest(&mut self) {
+        let start = self.verticies.iter().enumerate()
+            .min_by(|(_, a), (_, b)| a.1.total_cmp(&b.1).then(a.0.total_cmp(&b.0)))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        self.verticies.rotate_left(start);
     }
 
     fn border_vertices(&self) -> Vec<[f32; 3]> {
         self.verticies.iter().map(|(x, y)| [*x, *y, 0.0]).collect()
     }
 
-    fn vertices_indices(&self) -> (Vec<[f32; 3]>, Vec<u32>) {
+    fn vertices_indices(&self, color: (u8, u8, u8)) -> Result<(Vec<[f32; 3]>, Vec<u32>), Error> {
         let mut raw_verticies = vec![self.verticies.iter().map(|(x, y)| vec![*x, *y]).collect::<Vec<_>>()];
 
         for hole in self.holes.iter() {
@@ -354,86 +549,453 @@ impl RawPolygon {
         }
 
         let (vertices, holes, dimensions) = earcutr::flatten(&raw_verticies);
-        let triangles = earcutr::earcut(&vertices, &holes, dimensions).unwrap();
+        let triangles = earcutr::earcut(&vertices, &holes, dimensions)
+            .map_err(|_| Error::Triangulation { color, position: self.origin })?;
 
         let verticies: Vec<[f32; 3]> = vertices.chunks(2).map(|chunk| [chunk[0], chunk[1], 0.0]).collect();
 
-        (verticies, triangles.into_iter().map(|i| i as u32).collect())
+        Ok((verticies, triangles.into_iter().map(|i| i as u32).collect()))
     }
 }
 
-fn finish_polygons(polygons: HashMap<(u8, u8, u8), Vec<RawPolygon>>) -> Vec<Polygon> {
+/// For every ring, the index of the outer ring it is a hole in. `None` for outer rings.
+///
+/// Rings of one color never cross, so they form a tree by containment, with each ring under the smallest ring around it.
+/// A hole belongs to the ring directly above it.
+/// This keeps an island inside a lake inside the same province apart from the province around the lake
+pub(crate) fn hole_parents(color: (u8, u8, u8), rings: &[RawPolygon]) -> Result<Vec<Option<usize>>, Error> {
+    let mut samples = Vec::with_capacity(rings.len());
+    for ring in rings {
+        match ring.sample_point() {
+            Some(sample) => samples.push(sample),
+            None => return Err(Error::DegenerateRegion { color, position: ring.origin }),
+        }
+    }
+
+    // Every ring around a sample point is an ancestor in the tree, and the smallest of them is the parent
+    let areas: Vec<f64> = rings.iter().map(|ring| geometry::area(&ring.verticies)).collect();
+    let grid = SpatialGrid::new(rings.iter().map(|ring| ring.bounds));
+    let enclosing: Vec<Option<usize>> = (0..rings.len()).map(|i| {
+        grid.candidates(samples[i]).iter().copied()
+            .filter(|j| *j != i && areas[*j] > areas[i] && rings[*j].is_inside(samples[i]))
+            .min_by(|a, b| areas[*a].total_cmp(&areas[*b]).then(a.cmp(b)))
+    }).collect();
+
+    let mut parents = vec![None; rings.len()];
+    for (i, hole) in rings.iter().enumerate().filter(|(_, poly)| poly.is_hole) {
+        match enclosing[i] {
+            Some(parent) if !rings[parent].is_hole => parents[i] = Some(parent),
+            _ => return Err(Error::HoleWithoutParent { color, position: hole.origin }),
+        }
+    }
+
+    Ok(parents)
+}
+
+/// Moves every hole into its outer ring, returning the outer rings
+pub(crate) fn attach_holes(rings: Vec<RawPolygon>, parents: &[Option<usize>]) -> Vec<RawPolygon> {
+    let mut non_holes: Vec<Option<RawPolygon>> = Vec::with_capacity(rings.len());
+    let mut holes = Vec::new();
+    for (ring, parent) in rings.into_iter().zip(parents) {
+        match parent {
+            Some(parent) => {
+                holes.push((*parent, ring));
+                non_holes.push(None);
+            },
+            None => non_holes.push(Some(ring)),
+        }
+    }
+
+    for (parent, hole) in holes {
+        non_holes[parent].as_mut().unwrap().holes.push(hole);
+    }
+
+    non_holes.into_iter().flatten().collect()
+}
+
+/// Triangulates the outer rings of one color, with their holes, into a single polygon with a part per outer ring
+pub(crate) fn finish_polygon(color: (u8, u8, u8), non_holes: Vec<RawPolygon>) -> Result<Polygon, Error> {
+    let mut polygon = Polygon::new(color);
+
+    for poly in non_holes {
+        if poly.verticies.len() < 3 {
+            return Err(Error::DegenerateRegion { color, position: poly.origin });
+        }
+        let (vertices, indices) = poly.vertices_indices(color)?;
+
+        let (vertices_before, indices_before) = (polygon.vertices.len(), polygon.indicies.len());
+        polygon.vertices.extend_from_slice(&vertices);
+        polygon.indicies.extend(indices.into_iter().map(|i| i + vertices_before as u32));
+
+        let outer = poly.border_vertices();
+        let holes: Vec<Vec<[f32; 3]>> = poly.holes.iter().map(|hole| hole.border_vertices()).collect();
+        polygon.parts.push(PolygonPart {
+            area: (geometry::area(&outer) - holes.iter().map(|hole| geometry::area(hole)).sum::<f64>()) as f32,
+            bounds: poly.bounds,
+            indices: indices_before..polygon.indicies.len(),
+            outer: outer.clone(),
+            holes: holes.clone(),
+        });
+
+        polygon.border_vertices.push(outer);
+        polygon.arc_rings.push(poly.arcs.clone());
+        for (hole, border) in poly.holes.iter().zip(holes) {
+            polygon.border_vertices.push(border);
+            polygon.arc_rings.push(hole.arcs.clone());
+        }
+    }
+
+    Ok(polygon)
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn finish_polygons(polygons: BTreeMap<(u8, u8, u8), Vec<RawPolygon>>) -> Result<Vec<Polygon>, Error> {
     let mut finished_polygons: Vec<Polygon> = Vec::new();
 
     for (color, raw_polys) in polygons {
-        let holes = raw_polys.iter().filter(|poly| poly.is_hole).cloned().collect::<Vec<_>>();
-        let mut non_holes = raw_polys.into_iter().filter(|poly| !poly.is_hole).collect::<Vec<_>>();
+        let parents = hole_parents(color, &raw_polys)?;
+        finished_polygons.push(finish_polygon(color, attach_holes(raw_polys, &parents))?);
+    }
 
-        //println!("Finishing polygon: {:?}", color);
+    Ok(finished_polygons)
+}
 
-        let mut polygon = Polygon {
-            mat_handle: Handle::default(),
-            source_color: color,
-            vertices: Vec::new(),
-            border_vertices: Vec::new(),
-            indicies: Vec::new(),
-        };
+/// Colors are triangulated concurrently. Errors are collected in color order, so the same error as the serial path is returned
+#[cfg(feature = "parallel")]
+pub(crate) fn finish_polygons(polygons: BTreeMap<(u8, u8, u8), Vec<RawPolygon>>) -> Result<Vec<Polygon>, Error> {
+    let finished: Vec<Result<Polygon, Error>> = polygons.into_par_iter()
+        .map(|(color, raw_polys)| {
+            let parents = hole_parents(color, &raw_polys)?;
+            finish_polygon(color, attach_holes(raw_polys, &parents))
+        })
+        .collect();
+
+    finished.into_iter().collect()
+}
 
-        for hole in holes {
-            let mut found = false;
-            for non_hole in &mut non_holes {
-                let point_in_hole = (hole.point_inside.unwrap().x as f32, hole.point_inside.unwrap().y as f32);
-                if non_hole.is_inside(point_in_hole) {
-                    polygon.border_vertices.push(hole.border_vertices());
-                    non_hole.holes.push(hole);
-                    found = true;
-                    //println!("Found hole");
-                    break;
-                }
+/// The pixels covered by every color, leaving out void pixels
+#[cfg(feature = "parallel")]
+fn color_bounds(img: &impl PixelSource) -> BTreeMap<(u8, u8, u8), PixelRect> {
+    let width = img.width();
+    (0..img.height()).into_par_iter()
+        .fold(HashMap::new, |mut bounds: HashMap<(u8, u8, u8), PixelRect>, y| {
+            for x in (0..width).filter(|x| !img.is_void(*x, y)) {
+                let rect = PixelRect { x, y, width: 1, height: 1 };
+                bounds.entry(img.color(x, y))
+                    .and_modify(|b| *b = b.union(&rect))
+                    .or_insert(rect);
             }
-            if !found {
-                panic!("Hole not found");
+            bounds
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (color, rect) in b {
+                a.entry(color).and_modify(|r| *r = r.union(&rect)).or_insert(rect);
             }
+            a
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Traces the whole image in one go
+#[cfg(any(test, not(feature = "parallel")))]
+fn trace_serial(img: &impl PixelSource, options: &LoadOptions) -> BTreeMap<(u8, u8, u8), Vec<RawPolygon>> {
+    let mut borders = BorderMap::load(img, options);
+
+    let mut raw_polys: BTreeMap<(u8, u8, u8), Vec<RawPolygon>> = BTreeMap::new();
+    while let Some((poly, color)) = borders.pop_polygon() {
+        match raw_polys.get_mut(&color) {
+            Some(vec) => vec.push(poly),
+            None => { raw_polys.insert(color, vec![poly]); },
         }
+    }
+    raw_polys
+}
+
+/// Traces every color in its own window of the image, concurrently.
+///
+/// A color's rings only ever clear its own border flags, and a window around the color traces them exactly like the whole image does.
+/// Rings come out in the same order as the serial trace, since both start each ring at its lowest edge
+#[cfg(feature = "parallel")]
+fn trace_parallel(img: &impl PixelSource, options: &LoadOptions) -> BTreeMap<(u8, u8, u8), Vec<RawPolygon>> {
+    let size = (img.width(), img.height());
+    let bounds: Vec<((u8, u8, u8), PixelRect)> = color_bounds(img).into_iter().collect();
+
+    bounds.into_par_iter()
+        .map(|(color, rect)| {
+            let mut borders = BorderMap::load_window(img, rect.expand(1, size), options, |c| *c == color);
+            let mut rings = Vec::new();
+            while let Some((poly, _)) = borders.pop_polygon() {
+                rings.push(poly);
+            }
+            (color, rings)
+        })
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum YAxis {
+    #[default]
+    Up,
+    Down,
+}
+
+/// Which point of a pixel lands on whole world coordinates, before scaling
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PixelOrigin {
+    #[default]
+    Center,
+    /// The corner nearest to `origin`, so the map covers whole pixels from there
+    Corner,
+}
+
+/// Where traced vertices are placed. The default puts pixel centers at whole numbers, with y pointing up from the bottom row
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LoadOptions {
+    /// World position of the bottom left pixel, or of the top left one when y points down
+    pub origin: (f32, f32),
+    /// World units per pixel
+    pub scale: f32,
+    pub y_axis: YAxis,
+    pub pixel_origin: PixelOrigin,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions { origin: (0.0, 0.0), scale: 1.0, y_axis: YAxis::Up, pixel_origin: PixelOrigin::Center }
+    }
+}
+
+impl LoadOptions {
+    /// From pixel centers at whole numbers with y pointing up, as the tracer walks them, to world positions
+    pub(crate) fn world_transform(&self, image_height: u32) -> Affine {
+        let corner = match self.pixel_origin {
+            PixelOrigin::Center => 0.0,
+            PixelOrigin::Corner => 0.5,
+        };
+        let x = (self.origin.0 + self.scale * corner, self.scale);
+        let y = match self.y_axis {
+            YAxis::Up => (self.origin.1 + self.scale * corner, self.scale),
+            YAxis::Down => (self.origin.1 + self.scale * (image_height as f32 - 1.0 + corner), -self.scale),
+        };
+        Affine { shift: (0.0, 0.0), offset: (x.0, y.0), factor: (x.1, y.1) }
+    }
+}
+
+/// Traces and triangulates every color region of the image, except for void pixels. Panics if the image is malformed,
+/// see [`try_load_polygons`] for a version that reports the problem instead.
+///
+/// The output is deterministic: polygons are sorted by color, and every ring starts at its lowest, then leftmost, vertex.
+pub fn load_polygons(img: impl PixelSource) -> Vec<Polygon> {
+    load_polygons_with(img, &LoadOptions::default())
+}
+
+/// Like [`load_polygons`], with the vertices placed by `options`
+pub fn load_polygons_with(img: impl PixelSource, options: &LoadOptions) -> Vec<Polygon> {
+    match try_load_polygons_with(img, options) {
+        Ok(polygons) => polygons,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+pub fn try_load_polygons(img: impl PixelSource) -> Result<Vec<Polygon>, Error> {
+    try_load_polygons_with(img, &LoadOptions::default())
+}
+
+pub fn try_load_polygons_with(img: impl PixelSource, options: &LoadOptions) -> Result<Vec<Polygon>, Error> {
+    if img.width() == 0 || img.height() == 0 {
+        return Err(Error::EmptyImage);
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    let raw_polys = trace_serial(&img, options);
 
-        for poly in non_holes {
-            let (vertices, indices) = poly.vertices_indices();
+    #[cfg(feature = "parallel")]
+    let raw_polys = trace_parallel(&img, options);
 
-            let vertices_before = polygon.vertices.len();
-            polygon.vertices.extend_from_slice(&vertices);
-            polygon.indicies.extend(indices.into_iter().map(|i| i + vertices_before as u32));
+    let mut res = finish_polygons(raw_polys)?;
+    for poly in res.iter_mut() {
+        poly.rgb = img.rgb(poly.source_color);
+    }
+
+    Ok(res)
+}
+
+/// What changed between two sets of polygons, matched by color
+#[derive(Debug, Clone, Default)]
+pub struct PolygonDiff {
+    pub added: Vec<Polygon>,
+    pub removed: Vec<(u8, u8, u8)>,
+    pub changed: Vec<Polygon>,
+}
+
+impl PolygonDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
 
+pub fn diff_polygons(old: &[Polygon], new: &[Polygon]) -> PolygonDiff {
+    let old_by_color: HashMap<(u8, u8, u8), &Polygon> = old.iter().map(|poly| (poly.source_color, poly)).collect();
+    let new_colors: HashSet<(u8, u8, u8)> = new.iter().map(|poly| poly.source_color).collect();
 
-            polygon.border_vertices.push(poly.border_vertices());
-            //finished_polygons.push(Polygon::new((color.0, color.1, color.2), poly));
-            //break;
+    let mut diff = PolygonDiff::default();
+    for poly in new {
+        match old_by_color.get(&poly.source_color) {
+            Some(old_poly) if *old_poly == poly => (),
+            Some(_) => diff.changed.push(poly.clone()),
+            None => diff.added.push(poly.clone()),
         }
+    }
+    diff.removed = old.iter().map(|poly| poly.source_color).filter(|color| !new_colors.contains(color)).collect();
+    diff
+}
 
-        finished_polygons.push(polygon);
+impl PolygonDiff {
+    /// Patches a list of polygons sorted by color, like the output of [`load_polygons`], keeping it sorted
+    pub fn apply(&self, polygons: &mut Vec<Polygon>) {
+        polygons.retain(|poly| !self.removed.contains(&poly.source_color));
+        for poly in self.changed.iter().chain(&self.added) {
+            match polygons.binary_search_by_key(&poly.source_color, |p| p.source_color) {
+                Ok(i) => polygons[i] = poly.clone(),
+                Err(i) => polygons.insert(i, poly.clone()),
+            }
+        }
     }
+}
 
-    finished_polygons
+/// A rectangle of pixels, in image coordinates
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
-pub fn load_polygons(img: Image) -> Vec<Polygon> {
-    let before = std::time::Instant::now();
-    let mut borders = BorderMap::load(img);
-    println!("Loaded in {}ms", before.elapsed().as_millis());
+impl PixelRect {
+    // Grown by `by` pixels on every side, without leaving the image
+    fn expand(&self, by: u32, (width, height): (u32, u32)) -> PixelRect {
+        let (x0, y0) = (self.x.saturating_sub(by), self.y.saturating_sub(by));
+        let (x1, y1) = ((self.x + self.width + by).min(width), (self.y + self.height + by).min(height));
+        PixelRect { x: x0, y: y0, width: x1.saturating_sub(x0), height: y1.saturating_sub(y0) }
+    }
 
-    let mut raw_polys: HashMap<(u8, u8, u8), Vec<RawPolygon>> = HashMap::new();
+    fn union(&self, other: &PixelRect) -> PixelRect {
+        let (x0, y0) = (self.x.min(other.x), self.y.min(other.y));
+        let (x1, y1) = ((self.x + self.width).max(other.x + other.width), (self.y + self.height).max(other.y + other.height));
+        PixelRect { x: x0, y: y0, width: x1 - x0, height: y1 - y0 }
+    }
 
-    let before = std::time::Instant::now();
-    while let Some((poly, color)) = borders.pop_polygon() {
-        match raw_polys.get_mut(&color) {
-            Some(vec) => vec.push(poly),
-            None => { raw_polys.insert(color, vec![poly]); },
+    fn intersects(&self, other: &PixelRect) -> bool {
+        self.x < other.x + other.width && other.x < self.x + self.width
+            && self.y < other.y + other.height && other.y < self.y + self.height
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y..self.y + self.height).flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+}
+
+// The pixels covered by a traced polygon, from its border vertices
+fn pixel_bounds(poly: &Polygon, image_height: u32, to_world: &Affine) -> Option<PixelRect> {
+    let points = BoundingBox::from_points(poly.border_vertices.iter().flatten().map(|p| to_world.invert((p[0], p[1]))));
+    let ((min_x, min_y), (max_x, max_y)) = (points.min, points.max);
+    if min_x > max_x {
+        return None;
+    }
+
+    let (x0, x1) = ((min_x + 0.5).floor().max(0.0) as u32, (max_x - 0.5).ceil().max(0.0) as u32);
+    let (act_y0, act_y1) = ((min_y + 0.5).floor().max(0.0) as u32, (max_y - 0.5).ceil().max(0.0) as u32);
+    let y0 = image_height.saturating_sub(act_y1 + 1);
+    Some(PixelRect { x: x0, y: y0, width: x1 - x0 + 1, height: act_y1 - act_y0 + 1 })
+}
+
+/// Re-traces the colors around an edited rectangle of the image, instead of the whole image.
+///
+/// `previous` must be the output of [`load_polygons`] for the image before the edit, and `img` the image after it.
+/// Every color inside the rectangle before or after the edit, or next to it, is traced again over its whole region.
+/// Re-traced polygons keep the terrain of the polygon they replace, and are only reported if they differ from it
+pub fn retrace_polygons(previous: &[Polygon], img: &impl PixelSource, dirty: PixelRect) -> Result<PolygonDiff, Error> {
+    retrace_polygons_with(previous, img, dirty, &LoadOptions::default())
+}
+
+/// Like [`retrace_polygons`], for polygons loaded with [`load_polygons_with`] and the same `options`
+pub fn retrace_polygons_with(previous: &[Polygon], img: &impl PixelSource, dirty: PixelRect, options: &LoadOptions) -> Result<PolygonDiff, Error> {
+    let size = (img.width(), img.height());
+    let to_world = options.world_transform(size.1);
+    let dirty = dirty.expand(0, size);
+    if dirty.width == 0 || dirty.height == 0 {
+        return Ok(PolygonDiff::default());
+    }
+    // Corners are placed by looking at the diagonal neighbors, so colors next to the rectangle can change too
+    let around = dirty.expand(1, size);
+
+    let mut affected: HashSet<(u8, u8, u8)> = around.pixels()
+        .filter(|(x, y)| !img.is_void(*x, *y))
+        .map(|(x, y)| img.color(x, y))
+        .collect();
+
+    let bounds: Vec<Option<PixelRect>> = previous.iter().map(|poly| pixel_bounds(poly, size.1, &to_world)).collect();
+    for (poly, bounds) in previous.iter().zip(&bounds) {
+        if affected.contains(&poly.source_color) || !bounds.is_some_and(|b| b.intersects(&dirty)) {
+            continue;
+        }
+        let covered_before = dirty.pixels()
+            .any(|(x, y)| geometry::contains(&poly.border_vertices, to_world.apply((x as f32, (size.1 - y - 1) as f32))));
+        if covered_before {
+            affected.insert(poly.source_color);
+        }
+    }
+
+    // Whatever the edit did, an affected color's region is still within its old bounds and the rectangle
+    let mut window = around;
+    for (poly, bounds) in previous.iter().zip(&bounds) {
+        if let (true, Some(bounds)) = (affected.contains(&poly.source_color), bounds) {
+            window = window.union(&bounds.expand(1, size));
         }
     }
-    println!("Found all polygons in {}ms", before.elapsed().as_millis());
 
-    let before = std::time::Instant::now();
-    let res = finish_polygons(raw_polys);
-    println!("Finished polygons in {}ms", before.elapsed().as_millis());
+    let mut borders = BorderMap::load_window(img, window, options, |color| affected.contains(color));
+    let mut raw_polys: BTreeMap<(u8, u8, u8), Vec<RawPolygon>> = BTreeMap::new();
+    while let Some((poly, color)) = borders.pop_polygon() {
+        raw_polys.entry(color).or_default().push(poly);
+    }
+    let mut retraced = finish_polygons(raw_polys)?;
+
+    let old: Vec<&Polygon> = previous.iter().filter(|poly| affected.contains(&poly.source_color)).collect();
+    let old_terrain: HashMap<(u8, u8, u8), Option<TerrainType>> = old.iter().map(|poly| (poly.source_color, poly.terrain)).collect();
+    for poly in retraced.iter_mut() {
+        poly.rgb = img.rgb(poly.source_color);
+        if let Some(terrain) = old_terrain.get(&poly.source_color) {
+            poly.terrain = *terrain;
+        }
+    }
+    let old: Vec<Polygon> = old.into_iter().cloned().collect();
+    Ok(diff_polygons(&old, &retraced))
+}
 
-    res
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_matches_serial() {
+        let options = [
+            LoadOptions::default(),
+            LoadOptions { origin: (-3.0, 7.5), scale: 0.1, y_axis: YAxis::Down, pixel_origin: PixelOrigin::Corner },
+        ];
+        for entry in std::fs::read_dir("assets").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "bmp") {
+                continue;
+            }
+            let img = bmp::open(&path).unwrap();
+            for options in &options {
+                let serial = finish_polygons(trace_serial(&img, options));
+                let parallel = finish_polygons(trace_parallel(&img, options));
+                assert_eq!(serial, parallel, "{} with {:?}", path.display(), options);
+            }
+        }
+    }
 }
@@ -1,25 +1,98 @@
 use std::collections::HashMap;
 
-use bevy::{asset::{Handle, Asset, AssetApp}, reflect::TypePath, app::{App, Plugin}};
-
-use crate::{polygon::Polygon, border_segment::BorderSegment};
+use bevy::{asset::{Handle, Asset, AssetApp, Assets}, reflect::TypePath, app::{App, Plugin}, ecs::system::Resource};
+use crate::{adjacency::AdjacencyGraph, border_segment::BorderSegment, eu4::{color_polys_with, Definitions}, polygon::{LoadOptions, Polygon}, source::PixelSource, topology::load_topology_with, Error};
 
 pub struct ProvincePlugin;
 
 impl Plugin for ProvincePlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<Province>();
+        app.init_resource::<ProvinceMap>();
     }
 }
 
+/// Handles to every loaded province, by province ID and by map color
+#[derive(Resource, Default)]
 pub struct ProvinceMap {
     map: HashMap<u32, Handle<Province>>,
+    colors: HashMap<(u8, u8, u8), u32>,
+}
+
+impl ProvinceMap {
+    pub fn get(&self, id: u32) -> Option<&Handle<Province>> {
+        self.map.get(&id)
+    }
+
+    pub fn get_by_color(&self, color: (u8, u8, u8)) -> Option<&Handle<Province>> {
+        self.colors.get(&color).and_then(|id| self.map.get(id))
+    }
+
+    pub fn id_of(&self, color: (u8, u8, u8)) -> Option<u32> {
+        self.colors.get(&color).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Handle<Province>)> {
+        self.map.iter().map(|(id, handle)| (*id, handle))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
 }
 
 #[derive(Asset, TypePath)]
 pub struct Province {
-    id: u32,
-    polygons: Vec<Polygon>,
-    border_segments: Vec<BorderSegment>,
-    neighbors: Vec<u32>,
-}
\ No newline at end of file
+    pub id: u32,
+    pub polygons: Vec<Polygon>,
+    pub border_segments: Vec<BorderSegment>,
+    /// IDs of neighboring provinces, sorted by the color of the neighbor
+    pub neighbors: Vec<u32>,
+}
+
+/// Turns every polygon whose color is defined in colors.txt into a province asset.
+/// Polygons with undefined colors are skipped, and so are neighbors with undefined colors
+pub fn build_provinces(polygons: Vec<Polygon>, adjacency: &AdjacencyGraph, definitions: &Definitions, provinces: &mut Assets<Province>) -> ProvinceMap {
+    let mut province_map = ProvinceMap::default();
+
+    for polygon in polygons {
+        let id = match definitions.get(polygon.rgb) {
+            Some(def) => def.id,
+            None => continue,
+        };
+
+        let mut border_segments = Vec::new();
+        let mut neighbors = Vec::new();
+        for neighbor in adjacency.neighbors(polygon.source_color) {
+            if let Some(neighbor_id) = neighbor.id {
+                border_segments.push(BorderSegment { province_id: id, neighbor_id, length: neighbor.border_length });
+                neighbors.push(neighbor_id);
+            }
+        }
+
+        province_map.colors.insert(polygon.source_color, id);
+        let handle = provinces.add(Province { id, polygons: vec![polygon], border_segments, neighbors });
+        province_map.map.insert(id, handle);
+    }
+
+    province_map
+}
+
+/// Traces the bitmap and builds a colored province asset for every defined color
+pub fn load_provinces(img: impl PixelSource, definitions: &Definitions, provinces: &mut Assets<Province>) -> Result<ProvinceMap, Error> {
+    load_provinces_with(img, definitions, provinces, &LoadOptions::default())
+}
+
+/// Like [`load_provinces`], with the polygons placed by `options`
+pub fn load_provinces_with(img: impl PixelSource, definitions: &Definitions, provinces: &mut Assets<Province>, options: &LoadOptions) -> Result<ProvinceMap, Error> {
+    let topology = load_topology_with(&img, options)?;
+    let adjacency = AdjacencyGraph::new(&topology, definitions);
+    let mut polygons = topology.polygons()?;
+    color_polys_with(&mut polygons, definitions);
+
+    Ok(build_provinces(polygons, &adjacency, definitions, provinces))
+}
@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{geometry, topology::Topology};
+
+// Geometry is done in f64, half-pixel coordinates on big maps overflow the exact range of f32 products
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (p, a, b) = ((p.0 as f64, p.1 as f64), (a.0 as f64, a.1 as f64), (b.0 as f64, b.1 as f64));
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq == 0.0 { 0.0 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len_sq).clamp(0.0, 1.0) };
+    let (cx, cy) = (a.0 + t * dx, a.1 + t * dy);
+    ((p.0 - cx).powi(2) + (p.1 - cy).powi(2)).sqrt() as f32
+}
+
+// Strictly between a and b, given that p is on the line through them
+fn strictly_between(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> bool {
+    p != a && p != b && p.0 >= a.0.min(b.0) && p.0 <= a.0.max(b.0) && p.1 >= a.1.min(b.1) && p.1 <= a.1.max(b.1)
+}
+
+/// Whether two segments cross or overlap anywhere except at a shared end point
+fn conflicts((p1, p2): ((f32, f32), (f32, f32)), (q1, q2): ((f32, f32), (f32, f32))) -> bool {
+    let to64 = |p: (f32, f32)| (p.0 as f64, p.1 as f64);
+    let (p1, p2, q1, q2) = (to64(p1), to64(p2), to64(q1), to64(q2));
+
+    if (p1 == q1 && p2 == q2) || (p1 == q2 && p2 == q1) {
+        return true;
+    }
+
+    let (d1, d2) = (cross(q1, q2, p1), cross(q1, q2, p2));
+    let (d3, d4) = (cross(p1, p2, q1), cross(p1, p2, q2));
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0)) && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0)) {
+        return true;
+    }
+
+    (d1 == 0.0 && strictly_between(p1, q1, q2))
+        || (d2 == 0.0 && strictly_between(p2, q1, q2))
+        || (d3 == 0.0 && strictly_between(q1, p1, p2))
+        || (d4 == 0.0 && strictly_between(q2, p1, p2))
+}
+
+pub(crate) fn grid_cell(p: (f32, f32), cell_size: f32) -> (i32, i32) {
+    ((p.0 / cell_size).floor() as i32, (p.1 / cell_size).floor() as i32)
+}
+
+/// Segments that cross or overlap another segment, as the line and the index of the segment's first point
+pub(crate) fn crossing_segments(lines: &[Vec<(f32, f32)>], cell_size: f32) -> HashSet<(usize, usize)> {
+    let mut cells: HashMap<(i32, i32), Vec<(usize, usize)>> = HashMap::new();
+    for (id, line) in lines.iter().enumerate() {
+        for (k, w) in line.windows(2).enumerate() {
+            let (pa, pb) = (w[0], w[1]);
+            let (x0, y0) = grid_cell((pa.0.min(pb.0), pa.1.min(pb.1)), cell_size);
+            let (x1, y1) = grid_cell((pa.0.max(pb.0), pa.1.max(pb.1)), cell_size);
+            for cx in x0..=x1 {
+                for cy in y0..=y1 {
+                    cells.entry((cx, cy)).or_default().push((id, k));
+                }
+            }
+        }
+    }
+
+    let mut crossing = HashSet::new();
+    for cell in cells.values() {
+        for (n, s) in cell.iter().enumerate() {
+            for t in &cell[n + 1..] {
+                let seg_s = (lines[s.0][s.1], lines[s.0][s.1 + 1]);
+                let seg_t = (lines[t.0][t.1], lines[t.0][t.1 + 1]);
+                if conflicts(seg_s, seg_t) {
+                    crossing.insert(*s);
+                    crossing.insert(*t);
+                }
+            }
+        }
+    }
+    crossing
+}
+
+/// An arc being simplified. Closed arcs repeat their first point at the end, so both can be treated as open lines
+struct Line {
+    points: Vec<(f32, f32)>,
+    keep: Vec<bool>,
+    closed: bool,
+}
+
+impl Line {
+    // The point between two kept points that is furthest from the segment joining them
+    fn furthest_between(&self, a: usize, b: usize) -> Option<(usize, f32)> {
+        (a + 1..b)
+            .map(|i| (i, distance_to_segment(self.points[i], self.points[a], self.points[b])))
+            .max_by(|x, y| x.1.total_cmp(&y.1))
+    }
+
+    /// Keeps the points already marked, and whatever else Douglas-Peucker needs between them
+    fn douglas_peucker(&mut self, tolerance: f32) {
+        let last = self.points.len() - 1;
+        self.keep[0] = true;
+        self.keep[last] = true;
+
+        let mut stack: Vec<(usize, usize)> = self.kept().windows(2).map(|w| (w[0], w[1])).collect();
+        while let Some((a, b)) = stack.pop() {
+            if let Some((i, distance)) = self.furthest_between(a, b) {
+                if distance > tolerance {
+                    self.keep[i] = true;
+                    stack.push((a, i));
+                    stack.push((i, b));
+                }
+            }
+        }
+    }
+
+    fn kept(&self) -> Vec<usize> {
+        (0..self.points.len()).filter(|i| self.keep[*i]).collect()
+    }
+
+    /// Restores the dropped point that strays furthest from the simplified line. False if nothing is left to restore
+    fn refine(&mut self) -> bool {
+        let kept = self.kept();
+        let best = kept.windows(2)
+            .filter_map(|w| self.furthest_between(w[0], w[1]))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        match best {
+            Some((i, _)) => {
+                self.keep[i] = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Restores the furthest dropped point between the kept points `a` and `b`
+    fn split(&mut self, a: usize, b: usize) -> bool {
+        match self.furthest_between(a, b) {
+            Some((i, _)) => {
+                self.keep[i] = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Number of distinct vertices this line adds to a ring
+    fn ring_vertices(&self) -> usize {
+        self.keep.iter().filter(|k| **k).count() - 1
+    }
+}
+
+impl Topology {
+    /// Simplifies every arc with Douglas-Peucker, dropping vertices closer than `tolerance` pixels to the simplified border.
+    ///
+    /// Node positions never move, so neighbors stay watertight. Every ring keeps at least three vertices,
+    /// and vertices are restored wherever the simplified borders would cross or overlap.
+    pub fn simplify(&mut self, tolerance: f32) {
+        let corners = self.map_corners();
+
+        let mut lines: Vec<Line> = self.arcs.iter().map(|arc| {
+            let mut points = arc.points.clone();
+            if arc.is_closed() {
+                points.push(points[0]);
+            }
+            // Pin the corners of the map, so the map keeps its shape
+            let keep = points.iter().map(|p| corners.contains(p)).collect();
+            let mut line = Line { points, keep, closed: arc.is_closed() };
+            line.douglas_peucker(tolerance);
+            line
+        }).collect();
+
+        // Closed arcs and arcs looping back to their own node need two vertices besides the end point to enclose anything
+        for (line, arc) in lines.iter_mut().zip(&self.arcs) {
+            let loops = line.closed || arc.start == arc.end;
+            while loops && line.ring_vertices() < 3 && line.refine() {}
+        }
+
+        for ring in &self.rings {
+            while ring.arcs.iter().map(|r| lines[r.arc].ring_vertices()).sum::<usize>() < 3 {
+                if !ring.arcs.iter().any(|r| lines[r.arc].refine()) {
+                    break;
+                }
+            }
+        }
+
+        // Restore vertices until no simplified segments cross, and no segment has jumped over another vertex.
+        // The original borders satisfy both, so this terminates
+        let cell_size = (tolerance * 4.0).max(8.0);
+        loop {
+            let kept: Vec<Vec<usize>> = lines.iter().map(|line| line.kept()).collect();
+            let kept_points: Vec<Vec<(f32, f32)>> = lines.iter().zip(&kept)
+                .map(|(line, kept)| kept.iter().map(|i| line.points[*i]).collect())
+                .collect();
+
+            let mut splits: HashSet<(usize, usize, usize)> = crossing_segments(&kept_points, cell_size).into_iter()
+                .map(|(id, k)| (id, kept[id][k], kept[id][k + 1]))
+                .collect();
+
+            let mut point_cells: HashMap<(i32, i32), Vec<(usize, usize)>> = HashMap::new();
+            for (id, kept) in kept.iter().enumerate() {
+                for i in kept {
+                    point_cells.entry(grid_cell(lines[id].points[*i], cell_size)).or_default().push((id, *i));
+                }
+            }
+
+            // The dropped points and the segment replacing them enclose an area no other vertex may be in
+            for (id, kept) in kept.iter().enumerate() {
+                for w in kept.windows(2).filter(|w| w[1] - w[0] >= 2) {
+                    let (a, b) = (w[0], w[1]);
+                    let swept = &lines[id].points[a..=b];
+                    let (min_x, max_x) = swept.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+                    let (min_y, max_y) = swept.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+                    let ((x0, y0), (x1, y1)) = (grid_cell((min_x, min_y), cell_size), grid_cell((max_x, max_y), cell_size));
+                    let jumped = (x0..=x1).flat_map(|cx| (y0..=y1).map(move |cy| (cx, cy)))
+                        .filter_map(|c| point_cells.get(&c))
+                        .flatten()
+                        .any(|(other, i)| {
+                            let p = lines[*other].points[*i];
+                            !(*other == id && (a..=b).contains(i)) && p != swept[0] && p != swept[swept.len() - 1] && geometry::contains([swept], p)
+                        });
+                    if jumped {
+                        splits.insert((id, a, b));
+                    }
+                }
+            }
+
+            let mut changed = false;
+            for (id, a, b) in splits {
+                changed |= lines[id].split(a, b);
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        for (arc, line) in self.arcs.iter_mut().zip(lines) {
+            let mut points: Vec<(f32, f32)> = line.kept().into_iter().map(|i| line.points[i]).collect();
+            if line.closed {
+                points.pop();
+            }
+            arc.points = points;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::topology::load_topology;
+
+    /// Checks what simplifying and smoothing promise: no crossing borders, rings of three or more vertices,
+    /// holes inside their parents, and polygons that still tile the whole map
+    pub(crate) fn assert_valid(topology: &Topology, context: &str) {
+        let lines: Vec<Vec<(f32, f32)>> = topology.arcs.iter().map(|arc| {
+            let mut line = arc.points.clone();
+            if arc.is_closed() {
+                line.push(line[0]);
+            }
+            line
+        }).collect();
+        assert!(crossing_segments(&lines, 8.0).is_empty(), "{}: borders cross", context);
+
+        let rings: Vec<Vec<(f32, f32)>> = topology.rings.iter().map(|ring| topology.ring_vertices(ring)).collect();
+        for (ring, vertices) in topology.rings.iter().zip(&rings) {
+            assert!(vertices.len() >= 3, "{}: ring of {:?} has {} vertices", context, ring.color, vertices.len());
+            if let Some(parent) = ring.parent {
+                let outer = &rings[parent];
+                assert!(
+                    vertices.iter().filter(|p| !outer.contains(p)).all(|p| geometry::contains([outer], *p)),
+                    "{}: hole of {:?} leaves its parent", context, ring.color,
+                );
+            }
+        }
+
+        let area: f64 = topology.polygons().unwrap().iter().flat_map(|poly| &poly.parts).map(|part| part.area as f64).sum();
+        let map_area = (topology.width * topology.height) as f64;
+        assert!((area - map_area).abs() < map_area * 1e-5, "{}: polygons cover {} of {}", context, area, map_area);
+    }
+
+    #[test]
+    fn simplified_borders_stay_valid() {
+        for path in ["assets/map.bmp", "assets/dktst.bmp"] {
+            let original = load_topology(bmp::open(path).unwrap()).unwrap();
+            for tolerance in [0.5, 1.0, 1.5, 3.0, 8.0] {
+                let mut topology = original.clone();
+                topology.simplify(tolerance);
+                let context = format!("{} at {}", path, tolerance);
+                assert_valid(&topology, &context);
+
+                // Kept points are a subsequence of the original ones, so every dropped point has a segment replacing it
+                for (arc, simplified) in original.arcs.iter().zip(&topology.arcs) {
+                    let (mut before, mut after) = (arc.points.clone(), simplified.points.clone());
+                    if arc.is_closed() {
+                        before.push(before[0]);
+                        after.push(after[0]);
+                    }
+                    assert_eq!((before[0], before[before.len() - 1]), (after[0], after[after.len() - 1]), "{}", context);
+                    let mut segment = 0;
+                    for p in &before[1..] {
+                        let distance = distance_to_segment(*p, after[segment], after[segment + 1]);
+                        assert!(distance <= tolerance + 1e-4, "{}: {:?} is {} from the border", context, p, distance);
+                        if *p == after[segment + 1] {
+                            segment += 1;
+                        }
+                    }
+                    assert_eq!(segment, after.len() - 1, "{}", context);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{geometry, simplify::{crossing_segments, grid_cell}, topology::Topology};
+
+fn lerp(a: (f32, f32), b: (f32, f32), t: f32) -> (f32, f32) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+/// One round of Chaikin corner cutting on an open line. The end points stay where they are
+fn chaikin_open(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let last = points.len() - 2;
+    let mut smoothed = vec![points[0]];
+    for (i, w) in points.windows(2).enumerate() {
+        if i != 0 {
+            smoothed.push(lerp(w[0], w[1], 0.25));
+        }
+        if i != last {
+            smoothed.push(lerp(w[0], w[1], 0.75));
+        }
+    }
+    smoothed.push(points[points.len() - 1]);
+    smoothed
+}
+
+/// One round of Chaikin corner cutting on a closed ring
+fn chaikin_closed(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let mut smoothed = Vec::with_capacity(points.len() * 2);
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        smoothed.push(lerp(a, b, 0.25));
+        smoothed.push(lerp(a, b, 0.75));
+    }
+    smoothed
+}
+
+// Smooths the pieces of an arc between pinned map corners separately
+fn smooth_arc(points: &[(f32, f32)], is_closed: bool, corners: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    // Closed arcs touching a map corner are the whole map in one color, and are split there like open arcs
+    if is_closed && !points.iter().any(|p| corners.contains(p)) {
+        return chaikin_closed(points);
+    }
+
+    let mut points = points.to_vec();
+    if is_closed {
+        let start = points.iter().position(|p| corners.contains(p)).unwrap();
+        points.rotate_left(start);
+        points.push(points[0]);
+    }
+
+    let mut smoothed = vec![points[0]];
+    let mut piece_start = 0;
+    for i in 1..points.len() {
+        if i == points.len() - 1 || corners.contains(&points[i]) {
+            smoothed.extend_from_slice(&chaikin_open(&points[piece_start..=i])[1..]);
+            piece_start = i;
+        }
+    }
+
+    if is_closed {
+        smoothed.pop();
+    }
+    smoothed
+}
+
+/// The corners a round cuts off an arc, as the old corner between the two points replacing it
+fn cut_corners(points: &[(f32, f32)], is_closed: bool, corners: &[(f32, f32)]) -> Vec<[(f32, f32); 3]> {
+    let n = points.len();
+    let inner = if is_closed { 0..n } else { 1..n.saturating_sub(1) };
+    inner.filter(|i| !corners.contains(&points[*i]))
+        .map(|i| {
+            let (prev, corner, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            [lerp(prev, corner, 0.75), corner, lerp(corner, next, 0.25)]
+        })
+        .collect()
+}
+
+impl Topology {
+    /// Rounds off the pixel staircases with `iterations` rounds of Chaikin corner cutting.
+    ///
+    /// Each round doubles the vertex count, so simplify first on big maps. Nodes and the corners of the map
+    /// stay in place, and as arcs are shared, neighbors stay watertight. Arcs that would cut across another arc,
+    /// or cut off a corner with another arc's vertex in it, keep their previous shape in that round.
+    /// Run [`Topology::polygons`] afterwards to re-triangulate.
+    pub fn smooth(&mut self, iterations: usize) {
+        let corners = self.map_corners();
+
+        for _ in 0..iterations {
+            let previous: Vec<Vec<(f32, f32)>> = self.arcs.iter().map(|arc| arc.points.clone()).collect();
+            for arc in &mut self.arcs {
+                arc.points = smooth_arc(&arc.points, arc.is_closed(), &corners);
+            }
+
+            loop {
+                let lines: Vec<Vec<(f32, f32)>> = self.arcs.iter().map(|arc| {
+                    let mut line = arc.points.clone();
+                    if arc.is_closed() {
+                        line.push(line[0]);
+                    }
+                    line
+                }).collect();
+
+                let mut revert: HashSet<usize> = crossing_segments(&lines, 8.0).into_iter().map(|(id, _)| id).collect();
+
+                let mut point_cells: HashMap<(i32, i32), Vec<(usize, usize)>> = HashMap::new();
+                for (id, arc) in self.arcs.iter().enumerate() {
+                    for (i, p) in arc.points.iter().enumerate() {
+                        point_cells.entry(grid_cell(*p, 8.0)).or_default().push((id, i));
+                    }
+                }
+
+                // A cut corner can swallow a whole small arc, such as an island, without crossing it
+                let swallowing: Vec<usize> = self.arcs.iter().enumerate()
+                    .filter(|(id, arc)| arc.points != previous[*id])
+                    .filter(|(id, arc)| cut_corners(&previous[*id], arc.is_closed(), &corners).iter().any(|triangle| {
+                        let (min_x, max_x) = triangle.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+                        let (min_y, max_y) = triangle.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+                        let ((x0, y0), (x1, y1)) = (grid_cell((min_x, min_y), 8.0), grid_cell((max_x, max_y), 8.0));
+                        (x0..=x1).flat_map(|cx| (y0..=y1).map(move |cy| (cx, cy)))
+                            .filter_map(|c| point_cells.get(&c))
+                            .flatten()
+                            .any(|(other, i)| {
+                                let p = self.arcs[*other].points[*i];
+                                other != id && !triangle.contains(&p) && geometry::contains([triangle], p)
+                            })
+                    }))
+                    .map(|(id, _)| id)
+                    .collect();
+                revert.extend(swallowing);
+
+                let mut reverted = false;
+                for id in revert {
+                    if self.arcs[id].points != previous[id] {
+                        self.arcs[id].points = previous[id].clone();
+                        reverted = true;
+                    }
+                }
+                if !reverted {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{simplify::tests::assert_valid, source::RgbBuffer, topology::load_topology};
+
+    // A green block in the corner of a blue map, with a red pixel just inside the block's inner corner
+    fn island_in_corner() -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in 0..60 {
+            for x in 0..60 {
+                data.extend(match (x, y) {
+                    (38, 38) => [255, 0, 0],
+                    (0..40, 0..40) => [0, 255, 0],
+                    _ => [0, 0, 255],
+                });
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn corner_cuts_keep_islands() {
+        let data = island_in_corner();
+        let mut topology = load_topology(RgbBuffer::new(&data, 60, 60).unwrap()).unwrap();
+        for round in 1..=3 {
+            topology.smooth(1);
+            assert_valid(&topology, &format!("round {}", round));
+        }
+    }
+
+    #[test]
+    fn smoothed_borders_stay_valid() {
+        for path in ["assets/map.bmp", "assets/dktst.bmp"] {
+            let original = load_topology(bmp::open(path).unwrap()).unwrap();
+            for (tolerance, iterations) in [(0.0, 1), (1.5, 2), (3.0, 3)] {
+                let mut topology = original.clone();
+                topology.simplify(tolerance);
+                topology.smooth(iterations);
+                assert_valid(&topology, &format!("{} at {} after {} rounds", path, tolerance, iterations));
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use bmp::Image;
+
+/// Anything the tracer can read colors from, in image coordinates with the origin at the top left.
+/// Sources are read from several threads with the `parallel` feature, so they must be `Sync`
+pub trait PixelSource: Sync {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    /// Only called with coordinates inside the image
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8);
+    /// The RGB value a traced color stands for, for sources that trace something else such as palette indices
+    fn rgb(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        color
+    }
+    /// Void pixels are left out of every polygon, but still border their neighbors
+    fn is_void(&self, _x: u32, _y: u32) -> bool {
+        false
+    }
+}
+
+impl<S: PixelSource + ?Sized> PixelSource for &S {
+    fn width(&self) -> u32 {
+        (**self).width()
+    }
+
+    fn height(&self) -> u32 {
+        (**self).height()
+    }
+
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        (**self).color(x, y)
+    }
+
+    fn rgb(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        (**self).rgb(color)
+    }
+
+    fn is_void(&self, x: u32, y: u32) -> bool {
+        (**self).is_void(x, y)
+    }
+}
+
+impl PixelSource for Image {
+    fn width(&self) -> u32 {
+        self.get_width()
+    }
+
+    fn height(&self) -> u32 {
+        self.get_height()
+    }
+
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let pixel = self.get_pixel(x, y);
+        (pixel.r, pixel.g, pixel.b)
+    }
+}
+
+/// Fully transparent pixels are void, and alpha is otherwise ignored
+#[cfg(feature = "image")]
+impl PixelSource for image::RgbaImage {
+    fn width(&self) -> u32 {
+        self.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.height()
+    }
+
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let [r, g, b, _] = self.get_pixel(x, y).0;
+        (r, g, b)
+    }
+
+    fn is_void(&self, x: u32, y: u32) -> bool {
+        self.get_pixel(x, y).0[3] == 0
+    }
+}
+
+/// A raw buffer of tightly packed RGB bytes, row by row from the top
+#[derive(Debug, Copy, Clone)]
+pub struct RgbBuffer<'a> {
+    data: &'a [u8],
+    width: u32,
+    height: u32,
+}
+
+impl<'a> RgbBuffer<'a> {
+    /// `None` if the buffer is not exactly `width * height * 3` bytes long
+    pub fn new(data: &'a [u8], width: u32, height: u32) -> Option<Self> {
+        if data.len() != width as usize * height as usize * 3 {
+            return None;
+        }
+        Some(RgbBuffer { data, width, height })
+    }
+}
+
+impl PixelSource for RgbBuffer<'_> {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        (self.data[i], self.data[i + 1], self.data[i + 2])
+    }
+}
+
+/// Wraps a source to make some of its colors void, such as a background that should not become polygons.
+/// Colors are matched by their RGB value, and pixels the source already considers void stay void
+#[derive(Debug, Clone)]
+pub struct VoidColors<S> {
+    source: S,
+    colors: HashSet<(u8, u8, u8)>,
+}
+
+impl<S: PixelSource> VoidColors<S> {
+    pub fn new(source: S, colors: impl IntoIterator<Item = (u8, u8, u8)>) -> Self {
+        VoidColors { source, colors: colors.into_iter().collect() }
+    }
+}
+
+impl<S: PixelSource> PixelSource for VoidColors<S> {
+    fn width(&self) -> u32 {
+        self.source.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.source.height()
+    }
+
+    fn color(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        self.source.color(x, y)
+    }
+
+    fn rgb(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        self.source.rgb(color)
+    }
+
+    fn is_void(&self, x: u32, y: u32) -> bool {
+        self.source.is_void(x, y) || self.colors.contains(&self.rgb(self.color(x, y)))
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{geometry, polygon::{BoundingBox, Polygon, PolygonPart}};
+
+/// A uniform grid over a set of bounding boxes, for finding the boxes around a point without checking all of them.
+/// Items are identified by their index in the boxes it was built from
+#[derive(Debug, Clone)]
+pub struct SpatialGrid {
+    min: (f32, f32),
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl SpatialGrid {
+    /// Sizes the cells so there are about as many cells as boxes
+    pub fn new(boxes: impl IntoIterator<Item = BoundingBox>) -> Self {
+        let boxes: Vec<BoundingBox> = boxes.into_iter().collect();
+        let extent = BoundingBox::from_points(boxes.iter().flat_map(|b| [b.min, b.max]));
+        if boxes.is_empty() {
+            return SpatialGrid { min: (0.0, 0.0), cell_size: 1.0, columns: 0, rows: 0, cells: Vec::new() };
+        }
+
+        let (width, height) = ((extent.max.0 - extent.min.0).max(1.0), (extent.max.1 - extent.min.1).max(1.0));
+        let cell_size = (width * height / boxes.len() as f32).sqrt().max(1.0);
+        let columns = (width / cell_size) as usize + 1;
+        let rows = (height / cell_size) as usize + 1;
+
+        let mut grid = SpatialGrid { min: extent.min, cell_size, columns, rows, cells: vec![Vec::new(); columns * rows] };
+        for (id, b) in boxes.iter().enumerate() {
+            let ((x0, y0), (x1, y1)) = (grid.cell(b.min), grid.cell(b.max));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    grid.cells[y * columns + x].push(id);
+                }
+            }
+        }
+        grid
+    }
+
+    // Clamped, so points outside the grid land in the nearest cell
+    fn cell(&self, (x, y): (f32, f32)) -> (usize, usize) {
+        let column = ((x - self.min.0) / self.cell_size).max(0.0) as usize;
+        let row = ((y - self.min.1) / self.cell_size).max(0.0) as usize;
+        (column.min(self.columns - 1), row.min(self.rows - 1))
+    }
+
+    /// Items whose box might contain the point. Check the box itself before relying on it
+    pub fn candidates(&self, point: (f32, f32)) -> &[usize] {
+        if self.cells.is_empty() {
+            return &[];
+        }
+        let (x, y) = self.cell(point);
+        &self.cells[y * self.columns + x]
+    }
+}
+
+/// Answers which polygon contains a point, in the coordinates of the polygon vertices
+#[derive(Debug, Clone)]
+pub struct PolygonIndex {
+    grid: SpatialGrid,
+    /// Polygon and part index of every item in the grid
+    parts: Vec<(usize, usize)>,
+    polygons: Vec<((u8, u8, u8), Vec<PolygonPart>)>,
+}
+
+impl PolygonIndex {
+    pub fn new(polygons: &[Polygon]) -> Self {
+        let parts: Vec<(usize, usize)> = polygons.iter().enumerate()
+            .flat_map(|(i, poly)| (0..poly.parts.len()).map(move |k| (i, k)))
+            .collect();
+        let grid = SpatialGrid::new(parts.iter().map(|(i, k)| polygons[*i].parts[*k].bounds));
+        let polygons = polygons.iter().map(|poly| (poly.source_color, poly.parts.clone())).collect();
+        PolygonIndex { grid, parts, polygons }
+    }
+
+    /// Index of the polygon containing the point, in the slice the index was built from
+    pub fn polygon_at(&self, point: (f32, f32)) -> Option<usize> {
+        self.grid.candidates(point).iter()
+            .map(|id| self.parts[*id])
+            .find(|(i, k)| {
+                let part = &self.polygons[*i].1[*k];
+                part.bounds.contains(point) && geometry::contains(std::iter::once(&part.outer).chain(&part.holes), point)
+            })
+            .map(|(i, _)| i)
+    }
+
+    /// Source color of the polygon containing the point
+    pub fn color_at(&self, point: (f32, f32)) -> Option<(u8, u8, u8)> {
+        self.polygon_at(point).map(|i| self.polygons[i].0)
+    }
+}
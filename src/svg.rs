@@ -0,0 +1,149 @@
+use std::{collections::HashMap, fmt::Write as _, io};
+
+use crate::{eu4::{Definitions, TerrainType}, geojson::terrain_name, polygon::{BoundingBox, Polygon, YAxis}};
+
+/// Fill colors by terrain, for polygons classified by [`color_polys`](crate::eu4::color_polys) or found in the definitions
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TerrainPalette {
+    pub land: (u8, u8, u8),
+    pub sea: (u8, u8, u8),
+    pub lake: (u8, u8, u8),
+    /// Polygons with no known terrain
+    pub unknown: (u8, u8, u8),
+}
+
+impl Default for TerrainPalette {
+    /// The colors of the viewer
+    fn default() -> Self {
+        TerrainPalette { land: (50, 140, 64), sea: (80, 252, 252), lake: (80, 252, 252), unknown: (255, 255, 255) }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SvgFill {
+    /// The RGB value of the pixels each polygon was traced from
+    #[default]
+    SourceColor,
+    Terrain(TerrainPalette),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stroke {
+    pub color: (u8, u8, u8),
+    /// In the units of the vertices
+    pub width: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct SvgOptions {
+    pub fill: SvgFill,
+    /// Borders drawn around every polygon, none by default
+    pub stroke: Option<Stroke>,
+    /// Which way y points in the vertices. With [`YAxis::Up`], the default, the map is flipped to come out the right way up
+    pub y_axis: YAxis,
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn xml_escape(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+}
+
+// Path data for every ring of a polygon. Holes are cut out by the even-odd rule, whichever way they run
+fn path_data(poly: &Polygon, flip: Option<f32>) -> String {
+    let mut d = String::new();
+    for ring in &poly.border_vertices {
+        for (i, p) in ring.iter().enumerate() {
+            let y = flip.map_or(p[1], |top| top - p[1]);
+            let _ = write!(d, "{}{} {} ", if i == 0 { "M" } else { "L" }, p[0], y);
+        }
+        if !ring.is_empty() {
+            d.push_str("Z ");
+        }
+    }
+    d.pop();
+    d
+}
+
+/// An SVG image with a `<path>` per polygon, filled by `options` with the even-odd rule so holes stay open.
+///
+/// Each path has an `id` of `province-<id>` when the polygon's color is in `definitions`, `color-rrggbb` otherwise,
+/// and `data-color`, `data-province-id`, `data-name` and `data-terrain` attributes for whatever is known about it.
+/// When palette entries share an RGB value, the ids of their paths end in the traced color, as in `province-<id>-0000ff`
+/// for palette index 255
+pub fn to_svg(polygons: &[Polygon], definitions: &Definitions, options: &SvgOptions) -> String {
+    let bounds = BoundingBox::from_points(polygons.iter().flat_map(|poly| &poly.parts).flat_map(|part| [part.bounds.min, part.bounds.max]));
+    let (min, max) = if polygons.iter().all(|poly| poly.parts.is_empty()) { ((0.0, 0.0), (0.0, 0.0)) } else { (bounds.min, bounds.max) };
+    // Room for the outer half of the borders
+    let pad = options.stroke.map_or(0.0, |stroke| stroke.width / 2.0);
+    let (min, max) = ((min.0 - pad, min.1 - pad), (max.0 + pad, max.1 + pad));
+    let flip = match options.y_axis {
+        YAxis::Up => Some(min.1 + max.1),
+        YAxis::Down => None,
+    };
+    let (width, height) = (max.0 - min.0, max.1 - min.1);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" width=\"{}\" height=\"{}\">", min.0, min.1, width, height, width, height);
+    out.push_str("<g fill-rule=\"evenodd\"");
+    match options.stroke {
+        Some(stroke) => { let _ = write!(out, " stroke=\"{}\" stroke-width=\"{}\" stroke-linejoin=\"round\"", hex(stroke.color), stroke.width); },
+        None => out.push_str(" stroke=\"none\""),
+    }
+    out.push_str(">\n");
+
+    let mut shared: HashMap<(u8, u8, u8), usize> = HashMap::new();
+    for poly in polygons {
+        *shared.entry(poly.rgb).or_default() += 1;
+    }
+
+    for poly in polygons {
+        let definition = definitions.get(poly.rgb);
+        let terrain = definition.map(|def| def.terrain).or(poly.terrain);
+        let fill = match options.fill {
+            SvgFill::SourceColor => poly.rgb,
+            SvgFill::Terrain(palette) => match terrain {
+                Some(TerrainType::Land) => palette.land,
+                Some(TerrainType::Sea) => palette.sea,
+                Some(TerrainType::Lake) => palette.lake,
+                None => palette.unknown,
+            },
+        };
+
+        let color = hex(poly.rgb);
+        // Palette entries with the same RGB value are traced as separate regions, told apart by their traced color
+        let suffix = if shared[&poly.rgb] > 1 { format!("-{}", &hex(poly.source_color)[1..]) } else { String::new() };
+        match definition {
+            Some(def) => { let _ = write!(out, "<path id=\"province-{}{}\" data-province-id=\"{}\"", def.id, suffix, def.id); },
+            None => { let _ = write!(out, "<path id=\"color-{}{}\"", &color[1..], suffix); },
+        }
+        let _ = write!(out, " data-color=\"{}\"", color);
+        if let Some(def) = definition {
+            out.push_str(" data-name=\"");
+            xml_escape(&mut out, &def.name);
+            out.push('"');
+        }
+        if let Some(terrain) = terrain {
+            let _ = write!(out, " data-terrain=\"{}\"", terrain_name(terrain));
+        }
+        let _ = writeln!(out, " fill=\"{}\" d=\"{}\"/>", hex(fill), path_data(poly, flip));
+    }
+
+    out.push_str("</g>\n</svg>\n");
+    out
+}
+
+pub fn write_svg(mut writer: impl io::Write, polygons: &[Polygon], definitions: &Definitions, options: &SvgOptions) -> io::Result<()> {
+    writer.write_all(to_svg(polygons, definitions, options).as_bytes())
+}
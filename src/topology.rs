@@ -0,0 +1,447 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{geometry::signed_area, polygon::{attach_holes, finish_polygon, hole_parents, Affine, BoundingBox, LoadOptions, Polygon, RawPolygon}, source::PixelSource, Error};
+
+use Step::*;
+
+/// Unit steps along the pixel corner lattice, in counter-clockwise order
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Step {
+    East = 0,
+    North = 1,
+    West = 2,
+    South = 3,
+}
+
+const STEPS: [Step; 4] = [East, North, West, South];
+
+impl Step {
+    fn offset(&self) -> (isize, isize) {
+        match self {
+            East => (1, 0),
+            North => (0, 1),
+            West => (-1, 0),
+            South => (0, -1),
+        }
+    }
+
+    fn opposite(&self) -> Step {
+        match self {
+            East => West,
+            North => South,
+            West => East,
+            South => North,
+        }
+    }
+
+    // Lower is preferred. Turning left first keeps diagonally touching pixels in separate rings
+    fn turn_rank(&self, from: Step) -> u8 {
+        match (*self as u8 + 4 - from as u8) % 4 {
+            1 => 0, // Left
+            0 => 1, // Straight
+            3 => 2, // Right
+            _ => 3, // Back
+        }
+    }
+}
+
+// The color on one side of an edge, `None` outside the map and on void pixels
+type Side = Option<(u8, u8, u8)>;
+
+/// The colors of the image with y pointing up, like the traced polygons.
+/// Lattice vertex (i, j) is the bottom left corner of pixel (i, j)
+struct Grid {
+    width: usize,
+    height: usize,
+    /// `None` for void pixels, which are never traced but border their neighbors like the outside of the map
+    colors: Vec<Side>,
+}
+
+impl Grid {
+    fn load(img: &impl PixelSource) -> Self {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut colors = vec![None; width * height];
+        for y in 0..height {
+            let act_y = height - y - 1;
+            for x in 0..width {
+                if !img.is_void(x as u32, y as u32) {
+                    colors[act_y * width + x] = Some(img.color(x as u32, y as u32));
+                }
+            }
+        }
+        Grid { width, height, colors }
+    }
+
+    fn contains(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && x < self.width as isize && y < self.height as isize
+    }
+
+    fn color(&self, x: isize, y: isize) -> Side {
+        if !self.contains(x, y) {
+            return None;
+        }
+        self.colors[y as usize * self.width + x as usize]
+    }
+
+    // Pixels to the left and right of the edge taken by stepping from the vertex
+    fn sides(&self, (i, j): (isize, isize), step: Step) -> (Side, Side) {
+        match step {
+            East => (self.color(i, j), self.color(i, j - 1)),
+            North => (self.color(i - 1, j), self.color(i, j)),
+            West => (self.color(i - 1, j - 1), self.color(i - 1, j)),
+            South => (self.color(i, j - 1), self.color(i - 1, j - 1)),
+        }
+    }
+
+    // Pixel positions of the left and right sides, in the same order as `sides`
+    fn side_pixels(&self, (i, j): (isize, isize), step: Step) -> ((isize, isize), (isize, isize)) {
+        match step {
+            East => ((i, j), (i, j - 1)),
+            North => ((i - 1, j), (i, j)),
+            West => ((i - 1, j - 1), (i - 1, j)),
+            South => ((i, j - 1), (i - 1, j - 1)),
+        }
+    }
+
+    fn is_border(&self, vertex: (isize, isize), step: Step) -> bool {
+        let (left, right) = self.sides(vertex, step);
+        left != right
+    }
+
+    // Horizontal edges come first, then vertical ones
+    fn edge_index(&self, (i, j): (isize, isize), step: Step) -> usize {
+        let (i, j) = (i as usize, j as usize);
+        let horizontal = self.width * (self.height + 1);
+        match step {
+            East => j * self.width + i,
+            West => j * self.width + i - 1,
+            North => horizontal + j * (self.width + 1) + i,
+            South => horizontal + (j - 1) * (self.width + 1) + i,
+        }
+    }
+
+    fn edge_count(&self) -> usize {
+        self.width * (self.height + 1) + (self.width + 1) * self.height
+    }
+
+    fn image_position(&self, (x, y): (isize, isize)) -> (u32, u32) {
+        (x as u32, (self.height as isize - y - 1) as u32)
+    }
+}
+
+fn vertex_position((i, j): (isize, isize)) -> (f32, f32) {
+    (i as f32 - 0.5, j as f32 - 0.5)
+}
+
+/// A point where three or more regions meet, counting the outside of the map and void pixels as a region.
+/// Also placed where a region touches itself diagonally
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// In pixels, like the points of arcs
+    pub position: (f32, f32),
+}
+
+/// A stretch of border shared by exactly two regions.
+/// Walking the arc from start to end has `left` on the left side. `None` is outside the map or void
+#[derive(Debug, Clone)]
+pub struct BorderArc {
+    pub left: Option<(u8, u8, u8)>,
+    pub right: Option<(u8, u8, u8)>,
+    /// Start and end nodes. Both are `None` for closed arcs, such as the coast of an island in a single sea
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    /// Corner points, including both end points. Closed arcs don't repeat their first point.
+    /// In pixels, with pixel centers at whole numbers and y pointing up, whatever the [`LoadOptions`]
+    pub points: Vec<(f32, f32)>,
+    /// Length in pixel edges
+    pub length: u32,
+    ends: ((isize, isize), (isize, isize)),
+    first_step: Step,
+    last_step: Step,
+}
+
+impl BorderArc {
+    pub fn is_closed(&self) -> bool {
+        self.start.is_none()
+    }
+
+    // Lattice vertex and step the arc is entered with, when walked in the given direction
+    fn entry(&self, reversed: bool) -> ((isize, isize), Step) {
+        if reversed { (self.ends.1, self.last_step.opposite()) } else { (self.ends.0, self.first_step) }
+    }
+
+    fn exit(&self, reversed: bool) -> ((isize, isize), Step) {
+        if reversed { (self.ends.0, self.first_step.opposite()) } else { (self.ends.1, self.last_step) }
+    }
+}
+
+/// An arc walked in either direction as part of a ring
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ArcRef {
+    pub arc: usize,
+    pub reversed: bool,
+}
+
+/// A closed outline of one region, with the region on its left.
+/// Outer rings run counter-clockwise and holes clockwise
+#[derive(Debug, Clone)]
+pub struct TopoRing {
+    pub color: (u8, u8, u8),
+    pub arcs: Vec<ArcRef>,
+    pub is_hole: bool,
+    /// Index of the ring this is a hole in, resolved on the unsimplified borders
+    pub parent: Option<usize>,
+    point_inside: Option<(usize, usize)>,
+    pixel: (usize, usize),
+    origin: (u32, u32),
+}
+
+/// The borders of every region, split into arcs shared between neighbors.
+/// Changing an arc changes both regions it separates, so they can never drift apart.
+///
+/// Arcs are simplified and smoothed in pixels, and only placed by the [`LoadOptions`] when assembled into polygons
+#[derive(Debug, Clone)]
+pub struct Topology {
+    pub width: usize,
+    pub height: usize,
+    pub nodes: Vec<Node>,
+    pub arcs: Vec<BorderArc>,
+    /// Sorted by color, then in the order they were found
+    pub rings: Vec<TopoRing>,
+    /// RGB value of every traced color, from the source
+    rgb: HashMap<(u8, u8, u8), (u8, u8, u8)>,
+    /// From pixels to the vertices of the assembled polygons
+    to_world: Affine,
+}
+
+impl Topology {
+    /// The points of a ring in walking order, without repeating the first point. In pixels, like the arcs
+    pub fn ring_vertices(&self, ring: &TopoRing) -> Vec<(f32, f32)> {
+        ring_vertices(&self.arcs, &ring.arcs)
+    }
+
+    /// The RGB value a traced color stands for, see [`PixelSource::rgb`]
+    pub fn rgb(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        self.rgb.get(&color).copied().unwrap_or(color)
+    }
+
+    /// Corner points of the map itself, which smoothing and simplification leave in place
+    pub(crate) fn map_corners(&self) -> [(f32, f32); 4] {
+        let (right, top) = (self.width as f32 - 0.5, self.height as f32 - 0.5);
+        [(-0.5, -0.5), (right, -0.5), (-0.5, top), (right, top)]
+    }
+
+    fn raw_polygon(&self, ring: &TopoRing) -> RawPolygon {
+        let verticies: Vec<(f32, f32)> = self.ring_vertices(ring).into_iter().map(|p| self.to_world.apply(p)).collect();
+        let mut poly = RawPolygon {
+            is_hole: ring.is_hole,
+            bounds: BoundingBox::from_points(verticies.iter().copied()),
+            verticies,
+            point_inside: ring.point_inside.map(|(x, y)| self.to_world.apply((x as f32, y as f32))),
+            pixel: self.to_world.apply((ring.pixel.0 as f32, ring.pixel.1 as f32)),
+            origin: ring.origin,
+            arcs: ring.arcs.clone(),
+            holes: Vec::new(),
+        };
+        poly.rotate_to_lowest();
+        poly
+    }
+
+    /// Assembles and triangulates a polygon per color from the arcs, placed by the [`LoadOptions`] the topology was loaded with.
+    /// `arc_rings` is filled in on the result
+    pub fn polygons(&self) -> Result<Vec<Polygon>, Error> {
+        let mut polygons = Vec::new();
+        for (color, ring_ids) in rings_by_color(&self.rings) {
+            let raw_polys = ring_ids.iter().map(|id| self.raw_polygon(&self.rings[*id])).collect();
+            let parents: Vec<Option<usize>> = ring_ids.iter()
+                .map(|id| self.rings[*id].parent.map(|parent| ring_ids.iter().position(|other| *other == parent).unwrap()))
+                .collect();
+            let mut polygon = finish_polygon(color, attach_holes(raw_polys, &parents))?;
+            polygon.rgb = self.rgb(color);
+            polygons.push(polygon);
+        }
+        Ok(polygons)
+    }
+}
+
+// Walks from a vertex along the border until it hits a node, or comes back around to where it started
+fn trace_arc(grid: &Grid, used: &mut [bool], node_ids: &HashMap<(isize, isize), usize>, start: (isize, isize), first_step: Step) -> BorderArc {
+    let (left, right) = grid.sides(start, first_step);
+    let mut points = vec![vertex_position(start)];
+    let (mut vertex, mut step, mut length) = (start, first_step, 0);
+    loop {
+        used[grid.edge_index(vertex, step)] = true;
+        length += 1;
+        let (dx, dy) = step.offset();
+        vertex = (vertex.0 + dx, vertex.1 + dy);
+        if vertex == start || node_ids.contains_key(&vertex) {
+            break;
+        }
+
+        // Not a node, so exactly one way to continue
+        let next = STEPS.into_iter().find(|s| *s != step.opposite() && grid.is_border(vertex, *s)).unwrap();
+        if next != step {
+            points.push(vertex_position(vertex));
+        }
+        step = next;
+    }
+
+    let start_node = node_ids.get(&start).copied();
+    if start_node.is_some() {
+        points.push(vertex_position(vertex));
+    }
+
+    BorderArc {
+        left,
+        right,
+        start: start_node,
+        end: node_ids.get(&vertex).copied(),
+        points,
+        length,
+        ends: (start, vertex),
+        first_step,
+        last_step: step,
+    }
+}
+
+fn rings_by_color(rings: &[TopoRing]) -> BTreeMap<(u8, u8, u8), Vec<usize>> {
+    let mut by_color: BTreeMap<(u8, u8, u8), Vec<usize>> = BTreeMap::new();
+    for (id, ring) in rings.iter().enumerate() {
+        by_color.entry(ring.color).or_default().push(id);
+    }
+    by_color
+}
+
+fn ring_vertices(arcs: &[BorderArc], ring: &[ArcRef]) -> Vec<(f32, f32)> {
+    let mut vertices = Vec::new();
+    for arc_ref in ring {
+        let arc = &arcs[arc_ref.arc];
+        if arc.is_closed() {
+            if arc_ref.reversed {
+                vertices.extend(arc.points.iter().rev());
+            } else {
+                vertices.extend_from_slice(&arc.points);
+            }
+        } else if arc_ref.reversed {
+            vertices.extend(arc.points.iter().rev().take(arc.points.len() - 1));
+        } else {
+            vertices.extend_from_slice(&arc.points[..arc.points.len() - 1]);
+        }
+    }
+    vertices
+}
+
+/// Splits the borders of every color region into shared arcs, and assembles each region's rings from them. Void pixels are left out
+pub fn load_topology(img: impl PixelSource) -> Result<Topology, Error> {
+    load_topology_with(img, &LoadOptions::default())
+}
+
+/// Like [`load_topology`], with [`Topology::polygons`] placed by `options`
+pub fn load_topology_with(img: impl PixelSource, options: &LoadOptions) -> Result<Topology, Error> {
+    if img.width() == 0 || img.height() == 0 {
+        return Err(Error::EmptyImage);
+    }
+
+    let grid = Grid::load(&img);
+    let (width, height) = (grid.width as isize, grid.height as isize);
+
+    let mut nodes = Vec::new();
+    let mut node_ids = HashMap::new();
+    for j in 0..=height {
+        for i in 0..=width {
+            let degree = STEPS.iter().filter(|s| grid.is_border((i, j), **s)).count();
+            if degree >= 3 {
+                node_ids.insert((i, j), nodes.len());
+                nodes.push(Node { position: vertex_position((i, j)) });
+            }
+        }
+    }
+
+    let mut arcs = Vec::new();
+    let mut used = vec![false; grid.edge_count()];
+    for j in 0..=height {
+        for i in 0..=width {
+            if !node_ids.contains_key(&(i, j)) {
+                continue;
+            }
+            for step in STEPS {
+                if grid.is_border((i, j), step) && !used[grid.edge_index((i, j), step)] {
+                    arcs.push(trace_arc(&grid, &mut used, &node_ids, (i, j), step));
+                }
+            }
+        }
+    }
+
+    // Whatever is left are closed loops without any nodes
+    for j in 0..=height {
+        for i in 0..=width {
+            for step in [East, North] {
+                if grid.is_border((i, j), step) && !used[grid.edge_index((i, j), step)] {
+                    arcs.push(trace_arc(&grid, &mut used, &node_ids, (i, j), step));
+                }
+            }
+        }
+    }
+
+    let mut uses: BTreeMap<(u8, u8, u8), Vec<ArcRef>> = BTreeMap::new();
+    for (id, arc) in arcs.iter().enumerate() {
+        if let Some(color) = arc.left {
+            uses.entry(color).or_default().push(ArcRef { arc: id, reversed: false });
+        }
+        if let Some(color) = arc.right {
+            uses.entry(color).or_default().push(ArcRef { arc: id, reversed: true });
+        }
+    }
+
+    let rgb = uses.keys().map(|color| (*color, img.rgb(*color))).collect();
+    let mut rings = Vec::new();
+    for (color, uses) in uses {
+        let mut by_start: HashMap<(isize, isize), Vec<usize>> = HashMap::new();
+        for (k, arc_ref) in uses.iter().enumerate() {
+            by_start.entry(arcs[arc_ref.arc].entry(arc_ref.reversed).0).or_default().push(k);
+        }
+
+        let mut taken = vec![false; uses.len()];
+        for first in 0..uses.len() {
+            if taken[first] {
+                continue;
+            }
+            taken[first] = true;
+            let mut ring_arcs = vec![uses[first]];
+
+            if !arcs[uses[first].arc].is_closed() {
+                let mut current = uses[first];
+                loop {
+                    let (vertex, incoming) = arcs[current.arc].exit(current.reversed);
+                    let next = *by_start[&vertex].iter()
+                        .min_by_key(|k| arcs[uses[**k].arc].entry(uses[**k].reversed).1.turn_rank(incoming))
+                        .unwrap();
+                    if next == first {
+                        break;
+                    }
+                    taken[next] = true;
+                    ring_arcs.push(uses[next]);
+                    current = uses[next];
+                }
+            }
+
+            let (vertex, step) = arcs[uses[first].arc].entry(uses[first].reversed);
+            let (inside, outside) = grid.side_pixels(vertex, step);
+            let point_inside = grid.contains(outside.0, outside.1).then_some((outside.0 as usize, outside.1 as usize));
+            let is_hole = signed_area(&ring_vertices(&arcs, &ring_arcs)) < 0.0;
+            let pixel = (inside.0 as usize, inside.1 as usize);
+            rings.push(TopoRing { color, arcs: ring_arcs, is_hole, parent: None, point_inside, pixel, origin: grid.image_position(inside) });
+        }
+    }
+
+    let to_world = options.world_transform(grid.height as u32);
+    let mut topology = Topology { width: grid.width, height: grid.height, nodes, arcs, rings, rgb, to_world };
+    for (color, ring_ids) in rings_by_color(&topology.rings) {
+        let raw_polys: Vec<RawPolygon> = ring_ids.iter().map(|id| topology.raw_polygon(&topology.rings[*id])).collect();
+        for (id, parent) in ring_ids.iter().zip(hole_parents(color, &raw_polys)?) {
+            topology.rings[*id].parent = parent.map(|parent| ring_ids[parent]);
+        }
+    }
+
+    Ok(topology)
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use bmpoly::{adjacency::load_adjacency, bitmap::{from_bytes, Bitmap, BitmapError}, eu4::{color_polys_with, Definitions, ProvinceDefinition, TerrainType}, geojson::to_geojson, gltf::to_gltf, obj::to_obj, polygon::load_polygons, source::PixelSource, svg::{to_svg, SvgOptions}, topology::load_topology};
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+const PALETTE: [(u8, u8, u8); 4] = [(0, 0, 0), (255, 0, 0), (0, 200, 0), (10, 20, 30)];
+
+/// A BMP file with a 40 byte header. `masks` go right after the header, then the palette, then the rows,
+/// which are given in the order they are stored and padded here
+fn bmp_file(width: i32, height: i32, bits_per_pixel: u16, compression: u32, masks: &[u32], palette: &[(u8, u8, u8)], rows: &[Vec<u8>]) -> Vec<u8> {
+    let stride = (width as usize * bits_per_pixel as usize).div_ceil(32) * 4;
+    let data_offset = 14 + 40 + masks.len() * 4 + palette.len() * 4;
+
+    let mut bytes = b"BM".to_vec();
+    bytes.extend(((data_offset + stride * rows.len()) as u32).to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend((data_offset as u32).to_le_bytes());
+
+    bytes.extend(40u32.to_le_bytes());
+    bytes.extend(width.to_le_bytes());
+    bytes.extend(height.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(bits_per_pixel.to_le_bytes());
+    bytes.extend(compression.to_le_bytes());
+    bytes.extend([0; 12]);
+    bytes.extend((palette.len() as u32).to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+
+    masks.iter().for_each(|mask| bytes.extend(mask.to_le_bytes()));
+    palette.iter().for_each(|(r, g, b)| bytes.extend([*b, *g, *r, 0]));
+    for row in rows {
+        let mut row = row.clone();
+        row.resize(stride, 0);
+        bytes.extend(row);
+    }
+    bytes
+}
+
+// RGB values row by row from the top
+fn pixels(img: &Bitmap) -> Vec<Vec<(u8, u8, u8)>> {
+    (0..img.height()).map(|y| (0..img.width()).map(|x| img.rgb(img.color(x, y))).collect()).collect()
+}
+
+fn indices(img: &Bitmap) -> Vec<Vec<u8>> {
+    (0..img.height()).map(|y| (0..img.width()).map(|x| img.color(x, y).2).collect()).collect()
+}
+
+// Both row orders of the same image decode the same
+fn check_indexed(bits_per_pixel: u16, width: i32, top_down_rows: Vec<Vec<u8>>, expected: Vec<Vec<u8>>) {
+    let bottom_up_rows: Vec<Vec<u8>> = top_down_rows.iter().rev().cloned().collect();
+    let height = top_down_rows.len() as i32;
+    for (height, rows) in [(height, bottom_up_rows), (-height, top_down_rows)] {
+        let img = from_bytes(&bmp_file(width, height, bits_per_pixel, BI_RGB, &[], &PALETTE, &rows)).unwrap();
+        assert_eq!(img.palette(), Some(&PALETTE[..]));
+        assert_eq!((img.width(), img.height()), (width as u32, expected.len() as u32));
+        assert_eq!(indices(&img), expected);
+        let rgb: Vec<Vec<(u8, u8, u8)>> = expected.iter().map(|row| row.iter().map(|i| PALETTE[*i as usize]).collect()).collect();
+        assert_eq!(pixels(&img), rgb);
+    }
+}
+
+#[test]
+fn one_bit() {
+    // Wider than a byte, so a row spans two
+    check_indexed(1, 10, vec![vec![0b1010_0000, 0b0100_0000], vec![0b0000_0001, 0b1000_0000]], vec![
+        vec![1, 0, 1, 0, 0, 0, 0, 0, 0, 1],
+        vec![0, 0, 0, 0, 0, 0, 0, 1, 1, 0],
+    ]);
+}
+
+#[test]
+fn four_bit() {
+    check_indexed(4, 3, vec![vec![0x12, 0x30], vec![0x03, 0x20]], vec![vec![1, 2, 3], vec![0, 3, 2]]);
+}
+
+#[test]
+fn eight_bit() {
+    check_indexed(8, 3, vec![vec![3, 2, 1], vec![0, 1, 0], vec![2, 2, 3]], vec![vec![3, 2, 1], vec![0, 1, 0], vec![2, 2, 3]]);
+}
+
+fn pixels_16(values: &[u16]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+#[test]
+fn sixteen_bit_555() {
+    let rows = vec![pixels_16(&[0x7c00, 0x03e0]), pixels_16(&[0x001f, 0x0200])];
+    let img = from_bytes(&bmp_file(2, 2, 16, BI_RGB, &[], &[], &rows)).unwrap();
+    assert_eq!(img.palette(), None);
+    // Stored bottom-up
+    assert_eq!(pixels(&img), vec![vec![(0, 0, 255), (0, 132, 0)], vec![(255, 0, 0), (0, 255, 0)]]);
+}
+
+#[test]
+fn sixteen_bit_bitfields() {
+    let masks = [0xf800, 0x07e0, 0x001f];
+    let rows = vec![pixels_16(&[0xf800, 0x07e0, 0x001f, 0x0400])];
+    let img = from_bytes(&bmp_file(4, -1, 16, BI_BITFIELDS, &masks, &[], &rows)).unwrap();
+    assert_eq!(pixels(&img), vec![vec![(255, 0, 0), (0, 255, 0), (0, 0, 255), (0, 130, 0)]]);
+}
+
+#[test]
+fn rejects_wide_masks() {
+    let masks = [0xffff_ffff, 0x07e0, 0x001f];
+    let bytes = bmp_file(1, 1, 16, BI_BITFIELDS, &masks, &[], &[pixels_16(&[0])]);
+    assert!(matches!(from_bytes(&bytes), Err(BitmapError::Unsupported { bits_per_pixel: 16, compression: BI_BITFIELDS })));
+}
+
+#[test]
+fn rejects_truncated() {
+    let mut bytes = bmp_file(3, 2, 8, BI_RGB, &[], &PALETTE, &[vec![0, 1, 2], vec![2, 1, 0]]);
+    bytes.truncate(bytes.len() - 1);
+    assert!(matches!(from_bytes(&bytes), Err(BitmapError::Truncated)));
+    assert!(matches!(from_bytes(&bytes[..20]), Err(BitmapError::Truncated)));
+}
+
+// Red and green palette entries side by side, defined by their RGB value
+fn palette_map() -> (Bitmap, Definitions) {
+    let img = from_bytes(&bmp_file(4, 1, 8, BI_RGB, &[], &PALETTE, &[vec![1, 1, 2, 2]])).unwrap();
+    let definition = |id, terrain| ProvinceDefinition { id, name: format!("Province {}", id), terrain };
+    let provinces = HashMap::from([((255, 0, 0), definition(1, TerrainType::Land)), ((0, 200, 0), definition(2, TerrainType::Sea))]);
+    (img, Definitions { provinces })
+}
+
+#[test]
+fn palette_adjacency() {
+    let (img, definitions) = palette_map();
+    let adjacency = load_adjacency(&img, &definitions).unwrap();
+
+    let neighbors = adjacency.neighbors_of_id(1);
+    assert_eq!(neighbors.len(), 1);
+    assert_eq!((neighbors[0].color, neighbors[0].id, neighbors[0].terrain), ((0, 0, 2), Some(2), TerrainType::Sea));
+    assert_eq!(adjacency.neighbors_of_id(2)[0].id, Some(1));
+}
+
+#[test]
+fn palette_geojson() {
+    let (img, definitions) = palette_map();
+    for polygons in [load_polygons(&img), load_topology(&img).unwrap().polygons().unwrap()] {
+        let rgb: Vec<_> = polygons.iter().map(|poly| (poly.source_color, poly.rgb)).collect();
+        assert_eq!(rgb, vec![((0, 0, 1), (255, 0, 0)), ((0, 0, 2), (0, 200, 0))]);
+
+        let geojson = to_geojson(&polygons, &definitions);
+        assert!(geojson.contains("\"id\":1,") && geojson.contains("\"id\":2,"));
+        assert!(geojson.contains("\"color\":\"#ff0000\"") && geojson.contains("\"color\":\"#00c800\""));
+    }
+}
+
+#[test]
+fn palette_svg() {
+    let (img, definitions) = palette_map();
+    let svg = to_svg(&load_polygons(&img), &definitions, &SvgOptions::default());
+    assert!(svg.contains("id=\"province-1\"") && svg.contains("id=\"province-2\""));
+    assert!(svg.contains("data-color=\"#ff0000\"") && svg.contains("fill=\"#00c800\""));
+}
+
+#[test]
+fn palette_gltf_and_obj() {
+    let (img, definitions) = palette_map();
+    let polygons = load_polygons(&img);
+    let gltf = to_gltf(&polygons, &definitions);
+    assert!(gltf.contains("\"name\":\"Province 1\"") && gltf.contains("\"color\":\"#00c800\""));
+
+    let obj = to_obj(&polygons, &definitions);
+    assert!(obj.contains("o province_1\n") && obj.contains("o province_2\n"));
+    assert!(obj.lines().any(|line| line.starts_with('v') && line.ends_with(" 1 0 0")));
+}
+
+#[test]
+fn palette_terrain() {
+    let (img, definitions) = palette_map();
+    for mut polygons in [load_polygons(&img), load_topology(&img).unwrap().polygons().unwrap()] {
+        color_polys_with(&mut polygons, &definitions);
+        let terrain: Vec<_> = polygons.iter().map(|poly| poly.terrain).collect();
+        assert_eq!(terrain, vec![Some(TerrainType::Land), Some(TerrainType::Sea)]);
+    }
+}
+
+#[test]
+fn shared_palette_svg_ids() {
+    let (_, definitions) = palette_map();
+    // Entries 1 and 3 are the same red, on either side of the green entry 2
+    let palette = [(0, 0, 0), (255, 0, 0), (0, 200, 0), (255, 0, 0)];
+    let img = from_bytes(&bmp_file(6, 1, 8, BI_RGB, &[], &palette, &[vec![1, 1, 2, 2, 3, 3]])).unwrap();
+    let svg = to_svg(&load_polygons(&img), &definitions, &SvgOptions::default());
+
+    let ids: Vec<&str> = svg.split(" id=\"").skip(1).map(|rest| &rest[..rest.find('"').unwrap()]).collect();
+    assert_eq!(ids, vec!["province-1-000001", "province-2", "province-1-000003"]);
+    assert_eq!(svg.matches("data-province-id=\"1\"").count(), 2);
+}
@@ -0,0 +1,67 @@
+use std::{fs, path::PathBuf};
+
+use bmpoly::{adjacency::load_adjacency, cache::{content_hash, load_cache, save_cache, MapCache}, eu4::{color_polys_with, Definitions}, polygon::load_polygons, topology::load_topology};
+
+fn cache_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bmpoly-{}-{}.cache", name, std::process::id()))
+}
+
+// The traced map.bmp, and the hash of its sources
+fn traced_map() -> (MapCache, u64) {
+    let bytes = fs::read("assets/map.bmp").unwrap();
+    let img = bmp::open("assets/map.bmp").unwrap();
+    let definitions = Definitions::load(".").unwrap();
+    let mut polygons = load_polygons(&img);
+    color_polys_with(&mut polygons, &definitions);
+    let cache = MapCache { polygons, adjacency: load_adjacency(&img, &definitions).unwrap() };
+    (cache, content_hash(&bytes, ".").unwrap())
+}
+
+#[test]
+fn round_trip() {
+    let (traced, hash) = traced_map();
+    // Polygons built from a topology also carry their arcs
+    let img = bmp::open("assets/map.bmp").unwrap();
+    let topology = MapCache { polygons: load_topology(&img).unwrap().polygons().unwrap(), ..traced.clone() };
+    assert!(topology.polygons.iter().all(|poly| !poly.arc_rings.is_empty()));
+
+    for (name, cache) in [("round-trip", traced), ("round-trip-topology", topology)] {
+        let path = cache_path(name);
+        save_cache(&path, hash, &cache).unwrap();
+        let loaded = load_cache(&path, hash);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap().unwrap();
+        assert!(!loaded.polygons.is_empty() && !loaded.adjacency.neighbors.is_empty());
+        assert_eq!(loaded, cache);
+    }
+}
+
+#[test]
+fn truncated_is_an_error() {
+    let (cache, hash) = traced_map();
+    let path = cache_path("truncated");
+    save_cache(&path, hash, &cache).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    let loaded = load_cache(&path, hash);
+    fs::remove_file(&path).unwrap();
+
+    assert!(loaded.is_err());
+}
+
+#[test]
+fn other_sources_are_ignored() {
+    let (cache, hash) = traced_map();
+    let path = cache_path("other-sources");
+    save_cache(&path, hash, &cache).unwrap();
+    let loaded = load_cache(&path, hash ^ 1);
+    fs::remove_file(&path).unwrap();
+
+    assert!(loaded.unwrap().is_none());
+}
+
+#[test]
+fn missing_is_ignored() {
+    assert!(load_cache(cache_path("missing"), 0).unwrap().is_none());
+}
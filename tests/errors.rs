@@ -0,0 +1,24 @@
+use bmpoly::{polygon::try_load_polygons, source::RgbBuffer, topology::load_topology, Error};
+
+#[test]
+fn empty_image() {
+    for (width, height) in [(0, 0), (0, 5), (5, 0)] {
+        let img = RgbBuffer::new(&[], width, height).unwrap();
+        assert_eq!(try_load_polygons(img).unwrap_err(), Error::EmptyImage);
+        assert_eq!(load_topology(img).unwrap_err(), Error::EmptyImage);
+    }
+}
+
+#[test]
+fn display_names_color_and_pixel() {
+    let errors = [
+        Error::HoleWithoutParent { color: (1, 2, 3), position: (40, 50) },
+        Error::Triangulation { color: (1, 2, 3), position: (40, 50) },
+        Error::DegenerateRegion { color: (1, 2, 3), position: (40, 50) },
+    ];
+    for err in errors {
+        let message = err.to_string();
+        assert!(message.contains("(1, 2, 3)") && message.contains("(40, 50)"), "{}", message);
+    }
+    assert_eq!(Error::EmptyImage.to_string(), "image is empty");
+}
@@ -0,0 +1,69 @@
+use bmp::{Image, Pixel};
+use bmpoly::{polygon::{load_polygons, Polygon}, topology::load_topology};
+
+const PROVINCE: (u8, u8, u8) = (65, 194, 88);
+const LAKE: (u8, u8, u8) = (48, 250, 250);
+const POND: (u8, u8, u8) = (49, 200, 200);
+
+fn fill(img: &mut Image, (x0, y0, x1, y1): (u32, u32, u32, u32), (r, g, b): (u8, u8, u8)) {
+    for y in y0..y1 {
+        for x in x0..x1 {
+            img.set_pixel(x, y, Pixel::new(r, g, b));
+        }
+    }
+}
+
+// A province with a lake, an island of the same province in the lake, and a pond on the island
+fn nested() -> Image {
+    let mut img = Image::new(12, 12);
+    fill(&mut img, (0, 0, 12, 12), PROVINCE);
+    fill(&mut img, (2, 2, 10, 10), LAKE);
+    fill(&mut img, (4, 4, 8, 8), PROVINCE);
+    fill(&mut img, (5, 5, 7, 7), POND);
+    img
+}
+
+fn check_parts(polygons: &[Polygon], pixels: u32) {
+    let area: f32 = polygons.iter().flat_map(|poly| &poly.parts).map(|part| part.area).sum();
+    assert_eq!(area, pixels as f32);
+
+    for part in polygons.iter().flat_map(|poly| &poly.parts) {
+        assert!(part.area > 0.0);
+        for hole in &part.holes {
+            assert!(hole.iter().all(|p| part.bounds.contains((p[0], p[1]))));
+        }
+    }
+}
+
+fn province(polygons: &[Polygon]) -> &Polygon {
+    polygons.iter().find(|poly| poly.source_color == PROVINCE).unwrap()
+}
+
+#[test]
+fn holes_bmp() {
+    let img = bmp::open("assets/holes.bmp").unwrap();
+    let pixels = img.get_width() * img.get_height();
+    check_parts(&load_polygons(img.clone()), pixels);
+    check_parts(&load_topology(img).unwrap().polygons().unwrap(), pixels);
+}
+
+#[test]
+fn islands_bmp() {
+    let img = bmp::open("assets/islands.bmp").unwrap();
+    let pixels = img.get_width() * img.get_height();
+    check_parts(&load_polygons(img.clone()), pixels);
+    check_parts(&load_topology(img).unwrap().polygons().unwrap(), pixels);
+}
+
+#[test]
+fn island_in_lake_keeps_its_own_hole() {
+    for polygons in [load_polygons(nested()), load_topology(nested()).unwrap().polygons().unwrap()] {
+        check_parts(&polygons, 144);
+
+        let mut parts: Vec<(f32, usize)> = province(&polygons).parts.iter().map(|part| (part.area, part.holes.len())).collect();
+        parts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // The pond belongs to the island, not to the province around the lake
+        assert_eq!(parts.iter().map(|part| part.1).collect::<Vec<_>>(), vec![1, 1]);
+        assert!(parts[0].0 < 16.0 && parts[1].0 > 64.0);
+    }
+}
@@ -0,0 +1,47 @@
+#![cfg(all(feature = "bevy", feature = "image"))]
+
+use std::io::Cursor;
+
+use bmpoly::{loader::decode_map, polygon::load_polygons};
+use image::{ImageFormat, Rgba, RgbaImage};
+
+const PROVINCE: [u8; 4] = [65, 194, 88, 255];
+const SEA: [u8; 4] = [48, 250, 250, 255];
+const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+// Sea on the left, a province on the right, and a transparent column between them
+fn map() -> RgbaImage {
+    RgbaImage::from_fn(9, 4, |x, _| Rgba(match x {
+        0..=3 => SEA,
+        4 => TRANSPARENT,
+        _ => PROVINCE,
+    }))
+}
+
+fn encode(format: ImageFormat) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    map().write_to(&mut bytes, format).unwrap();
+    bytes.into_inner()
+}
+
+fn check(extension: &str, bytes: &[u8]) {
+    let img = decode_map(bytes, Some(extension)).unwrap();
+    assert_eq!((img.width(), img.height()), (9, 4));
+    assert!(img.is_void(4, 2));
+
+    let polygons = load_polygons(&*img);
+    let areas: Vec<((u8, u8, u8), f32)> = polygons.iter()
+        .map(|poly| (poly.source_color, poly.parts.iter().map(|part| part.area).sum()))
+        .collect();
+    assert_eq!(areas, vec![((48, 250, 250), 16.0), ((65, 194, 88), 16.0)]);
+}
+
+#[test]
+fn decodes_png() {
+    check("png", &encode(ImageFormat::Png));
+}
+
+#[test]
+fn decodes_tga() {
+    check("tga", &encode(ImageFormat::Tga));
+}
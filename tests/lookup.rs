@@ -0,0 +1,55 @@
+use bmpoly::{lookup::ProvinceLookup, polygon::{load_polygons_with, LoadOptions, PixelOrigin, YAxis}, source::{PixelSource, VoidColors}, spatial::PolygonIndex};
+
+fn options() -> Vec<LoadOptions> {
+    vec![
+        LoadOptions::default(),
+        LoadOptions { origin: (-3.0, 7.5), scale: 0.1, y_axis: YAxis::Down, pixel_origin: PixelOrigin::Corner },
+        LoadOptions { origin: (100.0, -20.0), scale: 4.0, y_axis: YAxis::Up, pixel_origin: PixelOrigin::Corner },
+    ]
+}
+
+// World position of a point within a pixel, `(0.0, 0.0)` being its center
+fn world(options: &LoadOptions, (x, y): (i64, i64), (dx, dy): (f32, f32), height: u32) -> (f32, f32) {
+    let corner = match options.pixel_origin {
+        PixelOrigin::Center => 0.0,
+        PixelOrigin::Corner => 0.5,
+    };
+    let row = match options.y_axis {
+        YAxis::Up => (height as i64 - 1 - y) as f32 + dy,
+        YAxis::Down => y as f32 - dy,
+    };
+    (options.origin.0 + options.scale * (x as f32 + dx + corner), options.origin.1 + options.scale * (row + corner))
+}
+
+fn check(img: &impl PixelSource) {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    for options in options() {
+        let lookup = ProvinceLookup::new(img).with_options(&options);
+        let index = PolygonIndex::new(&load_polygons_with(img, &options));
+        for y in -2..height + 2 {
+            for x in -2..width + 2 {
+                let on_map = x >= 0 && y >= 0 && x < width && y < height;
+                let expected = (on_map && !img.is_void(x as u32, y as u32)).then(|| img.color(x as u32, y as u32));
+                // Polygons cut across staircases, so they only agree with the pixels at pixel centers
+                let center = world(&options, (x, y), (0.0, 0.0), height as u32);
+                assert_eq!(index.color_at(center), expected, "pixel {:?} with {:?}", (x, y), options);
+                for offset in [(0.0, 0.0), (0.3, -0.2), (-0.4, 0.4)] {
+                    let point = world(&options, (x, y), offset, height as u32);
+                    assert_eq!(lookup.lookup(point), expected, "pixel {:?} at {:?} with {:?}", (x, y), point, options);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn matches_polygons() {
+    check(&bmp::open("assets/dktst.bmp").unwrap());
+}
+
+#[test]
+fn void_pixels() {
+    let img = bmp::open("assets/dktst.bmp").unwrap();
+    let pixel = img.get_pixel(img.get_width() / 2, img.get_height() / 2);
+    check(&VoidColors::new(img, [(pixel.r, pixel.g, pixel.b)]));
+}
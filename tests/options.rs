@@ -0,0 +1,63 @@
+use bmpoly::{polygon::{load_polygons_with, LoadOptions, PixelOrigin, Polygon, YAxis}, topology::load_topology_with};
+
+fn options() -> Vec<LoadOptions> {
+    vec![
+        LoadOptions::default(),
+        LoadOptions { origin: (100.0, -20.0), scale: 0.25, y_axis: YAxis::Up, pixel_origin: PixelOrigin::Corner },
+        LoadOptions { origin: (-3.0, 7.5), scale: 2.0, y_axis: YAxis::Down, pixel_origin: PixelOrigin::Center },
+        LoadOptions { origin: (0.0, 0.0), scale: 0.5, y_axis: YAxis::Down, pixel_origin: PixelOrigin::Corner },
+    ]
+}
+
+// The world rectangle covered by the image
+fn expected_bounds(options: &LoadOptions, (width, height): (u32, u32)) -> ((f32, f32), (f32, f32)) {
+    let corner = match options.pixel_origin {
+        PixelOrigin::Center => 0.5,
+        PixelOrigin::Corner => 0.0,
+    };
+    let min = (options.origin.0 - options.scale * corner, options.origin.1 - options.scale * corner);
+    (min, (min.0 + options.scale * width as f32, min.1 + options.scale * height as f32))
+}
+
+fn check_placement(polygons: &[Polygon], options: &LoadOptions, size: (u32, u32)) {
+    let parts = || polygons.iter().flat_map(|poly| &poly.parts);
+    let (min, max) = parts().fold(((f32::MAX, f32::MAX), (f32::MIN, f32::MIN)), |(min, max), part| {
+        ((min.0.min(part.bounds.min.0), min.1.min(part.bounds.min.1)), (max.0.max(part.bounds.max.0), max.1.max(part.bounds.max.1)))
+    });
+    let (expected_min, expected_max) = expected_bounds(options, size);
+    for (actual, expected) in [(min.0, expected_min.0), (min.1, expected_min.1), (max.0, expected_max.0), (max.1, expected_max.1)] {
+        assert!((actual - expected).abs() < 1e-3, "{options:?}: bound {actual} isn't {expected}");
+    }
+
+    let area: f32 = parts().map(|part| part.area).sum();
+    let expected = options.scale * options.scale * (size.0 * size.1) as f32;
+    assert!((area - expected).abs() < expected * 1e-4, "{options:?}: area {area} isn't {expected}");
+}
+
+#[test]
+fn polygons_are_placed_by_options() {
+    let img = bmp::open("assets/map.bmp").unwrap();
+    let size = (img.get_width(), img.get_height());
+    for options in options() {
+        check_placement(&load_polygons_with(&img, &options), &options, size);
+    }
+}
+
+#[test]
+fn topology_is_placed_by_options() {
+    let img = bmp::open("assets/map.bmp").unwrap();
+    let size = (img.get_width(), img.get_height());
+    for options in options() {
+        let mut topology = load_topology_with(&img, &options).unwrap();
+        check_placement(&topology.polygons().unwrap(), &options, size);
+
+        topology.simplify(1.5);
+        topology.smooth(1);
+        let polygons = topology.polygons().unwrap();
+        let (min, max) = expected_bounds(&options, size);
+        for part in polygons.iter().flat_map(|poly| &poly.parts) {
+            assert!(part.bounds.min.0 >= min.0 - 1e-3 && part.bounds.min.1 >= min.1 - 1e-3, "{options:?}");
+            assert!(part.bounds.max.0 <= max.0 + 1e-3 && part.bounds.max.1 <= max.1 + 1e-3, "{options:?}");
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use bmp::{Image, Pixel};
+use bmpoly::polygon::{load_polygons, retrace_polygons, PixelRect, Polygon, PolygonDiff};
+
+const LAND: (u8, u8, u8) = (0, 128, 0);
+const SEA: (u8, u8, u8) = (0, 0, 255);
+const HILLS: (u8, u8, u8) = (211, 211, 211);
+
+fn map() -> Image {
+    bmp::open("assets/map.bmp").unwrap()
+}
+
+fn paint(img: &mut Image, rect: PixelRect, (r, g, b): (u8, u8, u8), only: Option<(u8, u8, u8)>) {
+    for y in rect.y..rect.y + rect.height {
+        for x in rect.x..rect.x + rect.width {
+            let pixel = img.get_pixel(x, y);
+            if only.is_none_or(|color| (pixel.r, pixel.g, pixel.b) == color) {
+                img.set_pixel(x, y, Pixel::new(r, g, b));
+            }
+        }
+    }
+}
+
+// Pixels covered by a polygon, in image coordinates
+fn pixel_rect(poly: &Polygon, height: u32) -> PixelRect {
+    let (min, max) = poly.parts.iter().fold(((f32::MAX, f32::MAX), (f32::MIN, f32::MIN)), |(min, max), part| {
+        ((min.0.min(part.bounds.min.0), min.1.min(part.bounds.min.1)), (max.0.max(part.bounds.max.0), max.1.max(part.bounds.max.1)))
+    });
+    let (x0, x1) = ((min.0 + 0.5) as u32, (max.0 + 0.5) as u32);
+    let (y0, y1) = (height - (max.1 + 0.5) as u32, height - (min.1 + 0.5) as u32);
+    PixelRect { x: x0, y: y0, width: x1 - x0, height: y1 - y0 }
+}
+
+// Applies the diff of the edit to the polygons from before it, and checks they match a full trace of the edited image
+fn retrace(before: &Image, after: &Image, dirty: PixelRect) -> (PolygonDiff, Vec<Polygon>) {
+    let mut polygons = load_polygons(before);
+    let diff = retrace_polygons(&polygons, after, dirty).unwrap();
+    diff.apply(&mut polygons);
+    assert_eq!(polygons, load_polygons(after));
+    (diff, polygons)
+}
+
+#[test]
+fn unchanged() {
+    let img = map();
+    let (diff, _) = retrace(&img, &img, PixelRect { x: 190, y: 110, width: 20, height: 20 });
+    assert!(diff.is_empty());
+}
+
+#[test]
+fn adds_color() {
+    let before = map();
+    let mut after = before.clone();
+    let dirty = PixelRect { x: 200, y: 120, width: 6, height: 6 };
+    paint(&mut after, dirty, (250, 0, 250), None);
+
+    let (diff, _) = retrace(&before, &after, dirty);
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.added[0].source_color, (250, 0, 250));
+}
+
+#[test]
+fn removes_color() {
+    let before = map();
+    let hills = load_polygons(&before).into_iter().find(|poly| poly.source_color == HILLS).unwrap();
+    let dirty = pixel_rect(&hills, before.get_height());
+    let mut after = before.clone();
+    paint(&mut after, dirty, LAND, Some(HILLS));
+
+    let (diff, _) = retrace(&before, &after, dirty);
+    assert_eq!(diff.removed, vec![HILLS]);
+}
+
+#[test]
+fn splits_region() {
+    let before = map();
+    let land = load_polygons(&before).into_iter().find(|poly| poly.source_color == LAND).unwrap();
+    let largest = land.parts.iter().max_by(|a, b| a.area.total_cmp(&b.area)).unwrap();
+
+    // A channel of sea straight across the largest part of the land
+    let height = before.get_height();
+    let x = ((largest.bounds.min.0 + largest.bounds.max.0) / 2.0) as u32;
+    let (y0, y1) = (height - (largest.bounds.max.1 + 0.5) as u32, height - (largest.bounds.min.1 + 0.5) as u32);
+    let dirty = PixelRect { x, y: y0, width: 2, height: y1 - y0 };
+    let mut after = before.clone();
+    paint(&mut after, dirty, SEA, None);
+
+    let (diff, polygons) = retrace(&before, &after, dirty);
+    assert!(diff.changed.iter().any(|poly| poly.source_color == LAND));
+    let land_after = polygons.iter().find(|poly| poly.source_color == LAND).unwrap();
+    assert!(land_after.parts.len() > land.parts.len());
+}
@@ -0,0 +1,77 @@
+use bmp::{Image, Pixel};
+use bmpoly::{polygon::{load_polygons, BoundingBox}, spatial::{PolygonIndex, SpatialGrid}, topology::load_topology};
+
+const PROVINCE: (u8, u8, u8) = (65, 194, 88);
+const LAKE: (u8, u8, u8) = (48, 250, 250);
+const POND: (u8, u8, u8) = (49, 200, 200);
+
+fn fill(img: &mut Image, (x0, y0, x1, y1): (u32, u32, u32, u32), (r, g, b): (u8, u8, u8)) {
+    for y in y0..y1 {
+        for x in x0..x1 {
+            img.set_pixel(x, y, Pixel::new(r, g, b));
+        }
+    }
+}
+
+// A province with a lake, an island of the same province in the lake, and a pond on the island
+fn nested() -> Image {
+    let mut img = Image::new(12, 12);
+    fill(&mut img, (0, 0, 12, 12), PROVINCE);
+    fill(&mut img, (2, 2, 10, 10), LAKE);
+    fill(&mut img, (4, 4, 8, 8), PROVINCE);
+    fill(&mut img, (5, 5, 7, 7), POND);
+    img
+}
+
+#[test]
+fn points_in_holes() {
+    for polygons in [load_polygons(nested()), load_topology(nested()).unwrap().polygons().unwrap()] {
+        let index = PolygonIndex::new(&polygons);
+        // Pixel centers, with y pointing up from the bottom row
+        assert_eq!(index.color_at((0.0, 0.0)), Some(PROVINCE));
+        assert_eq!(index.color_at((2.0, 6.0)), Some(LAKE));
+        assert_eq!(index.color_at((4.0, 6.0)), Some(PROVINCE));
+        assert_eq!(index.color_at((5.0, 5.0)), Some(POND));
+        assert_eq!(index.color_at((5.9, 6.4)), Some(POND));
+        assert_eq!(index.color_at((-1.0, 5.0)), None);
+        assert_eq!(index.color_at((5.0, 12.0)), None);
+
+        let pond = index.polygon_at((6.0, 6.0)).unwrap();
+        assert_eq!(polygons[pond].source_color, POND);
+    }
+}
+
+#[test]
+fn every_pixel() {
+    let img = bmp::open("assets/map.bmp").unwrap();
+    let index = PolygonIndex::new(&load_polygons(&img));
+    let height = img.get_height();
+    for (x, y) in img.coordinates() {
+        let pixel = img.get_pixel(x, y);
+        assert_eq!(index.color_at((x as f32, (height - y - 1) as f32)), Some((pixel.r, pixel.g, pixel.b)), "pixel {:?}", (x, y));
+    }
+}
+
+#[test]
+fn empty() {
+    let index = PolygonIndex::new(&[]);
+    assert_eq!(index.polygon_at((0.0, 0.0)), None);
+    assert_eq!(index.color_at((10.0, -3.0)), None);
+    assert!(SpatialGrid::new([]).candidates((0.0, 0.0)).is_empty());
+}
+
+#[test]
+fn grid_candidates() {
+    let boxes = [
+        BoundingBox { min: (0.0, 0.0), max: (10.0, 10.0) },
+        BoundingBox { min: (20.0, 0.0), max: (30.0, 5.0) },
+        BoundingBox { min: (5.0, 5.0), max: (25.0, 8.0) },
+    ];
+    let grid = SpatialGrid::new(boxes);
+    for point in [(1.0, 1.0), (7.0, 6.0), (22.0, 2.0), (24.0, 7.5), (29.9, 4.9)] {
+        let candidates = grid.candidates(point);
+        for (id, b) in boxes.iter().enumerate().filter(|(_, b)| b.contains(point)) {
+            assert!(candidates.contains(&id), "{:?} misses box {:?}", point, b);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+
+use bmp::Image;
+use bmpoly::topology::{load_topology, ArcRef, BorderArc, Topology};
+
+type Color = (u8, u8, u8);
+
+const GREEN: Color = (65, 194, 88);
+const ORANGE: Color = (216, 162, 55);
+const MAGENTA: Color = (194, 65, 182);
+
+// Color of the pixel at a point in topology coordinates, `None` off the map
+fn region(img: &Image, (x, y): (f32, f32)) -> Option<Color> {
+    let (x, y) = (x.round() as i64, img.get_height() as i64 - 1 - y.round() as i64);
+    if x < 0 || y < 0 || x >= img.get_width() as i64 || y >= img.get_height() as i64 {
+        return None;
+    }
+    let pixel = img.get_pixel(x as u32, y as u32);
+    Some((pixel.r, pixel.g, pixel.b))
+}
+
+// The regions around a lattice vertex
+fn regions_at(img: &Image, (x, y): (f32, f32)) -> HashSet<Option<Color>> {
+    [(-0.25, -0.25), (0.25, -0.25), (-0.25, 0.25), (0.25, 0.25)].iter().map(|(dx, dy)| region(img, (x + dx, y + dy))).collect()
+}
+
+// The points of a ring walked along its arcs, like `Topology::ring_vertices`
+fn walk(arcs: &[BorderArc], ring: &[ArcRef]) -> Vec<(f32, f32)> {
+    let mut vertices = Vec::new();
+    for arc_ref in ring {
+        let arc = &arcs[arc_ref.arc];
+        let mut points = arc.points.clone();
+        if arc_ref.reversed {
+            points.reverse();
+        }
+        if !arc.is_closed() {
+            points.pop();
+        }
+        vertices.extend(points);
+    }
+    vertices
+}
+
+fn check_arcs(img: &Image, topology: &Topology) {
+    for arc in &topology.arcs {
+        assert_ne!(arc.left, arc.right);
+
+        let mut points = arc.points.clone();
+        if arc.is_closed() {
+            points.push(points[0]);
+        }
+        for pair in points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let middle = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+            let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+            let left = (-(y1 - y0) / length * 0.25, (x1 - x0) / length * 0.25);
+            assert_eq!(region(img, (middle.0 + left.0, middle.1 + left.1)), arc.left);
+            assert_eq!(region(img, (middle.0 - left.0, middle.1 - left.1)), arc.right);
+        }
+    }
+}
+
+fn check_nodes(img: &Image, topology: &Topology) {
+    let nodes: HashSet<(i64, i64)> = topology.nodes.iter().map(|node| ((node.position.0 * 2.0) as i64, (node.position.1 * 2.0) as i64)).collect();
+    assert_eq!(nodes.len(), topology.nodes.len());
+    for i in 0..=img.get_width() {
+        for j in 0..=img.get_height() {
+            let position = (i as f32 - 0.5, j as f32 - 0.5);
+            if regions_at(img, position).len() >= 3 {
+                assert!(nodes.contains(&((position.0 * 2.0) as i64, (position.1 * 2.0) as i64)), "no node at {:?}", position);
+            }
+        }
+    }
+    for node in &topology.nodes {
+        assert_eq!(regions_at(img, node.position).len(), 3, "node at {:?}", node.position);
+    }
+    for arc in topology.arcs.iter().filter(|arc| !arc.is_closed()) {
+        assert_eq!(topology.nodes[arc.start.unwrap()].position, arc.points[0]);
+        assert_eq!(topology.nodes[arc.end.unwrap()].position, *arc.points.last().unwrap());
+    }
+}
+
+fn check_arc_rings(topology: &Topology) {
+    for poly in topology.polygons().unwrap() {
+        assert_eq!(poly.arc_rings.len(), poly.border_vertices.len());
+        for (ring, arcs) in poly.border_vertices.iter().zip(&poly.arc_rings) {
+            assert!(arcs.iter().all(|arc_ref| {
+                let arc = &topology.arcs[arc_ref.arc];
+                (if arc_ref.reversed { arc.right } else { arc.left }) == Some(poly.source_color)
+            }));
+
+            let walked = walk(&topology.arcs, arcs);
+            let ring: Vec<(f32, f32)> = ring.iter().map(|p| (p[0], p[1])).collect();
+            assert_eq!(walked.len(), ring.len());
+            let start = walked.iter().position(|p| *p == ring[0]).unwrap();
+            assert!(walked.iter().cycle().skip(start).zip(&ring).all(|(a, b)| a == b), "{:?}", poly.source_color);
+        }
+    }
+}
+
+#[test]
+fn three_colors() {
+    let img = bmp::open("assets/3c.bmp").unwrap();
+    let topology = load_topology(&img).unwrap();
+
+    let pairs: HashSet<[Option<Color>; 2]> = topology.arcs.iter().map(|arc| {
+        let mut pair = [arc.left, arc.right];
+        pair.sort();
+        pair
+    }).collect();
+    let expected = HashSet::from([
+        [None, Some(GREEN)], [None, Some(MAGENTA)], [None, Some(ORANGE)],
+        [Some(GREEN), Some(MAGENTA)], [Some(GREEN), Some(ORANGE)], [Some(MAGENTA), Some(ORANGE)],
+    ]);
+    // Every pair of regions shares a single border, so each shows up as a single arc
+    assert_eq!(topology.arcs.len(), 6);
+    assert_eq!(pairs, expected);
+
+    let mut nodes: Vec<(f32, f32)> = topology.nodes.iter().map(|node| node.position).collect();
+    nodes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(nodes, vec![(-0.5, 1.5), (2.5, 2.5), (3.5, 5.5), (4.5, -0.5)]);
+
+    check_arcs(&img, &topology);
+    check_nodes(&img, &topology);
+    check_arc_rings(&topology);
+}
+
+#[test]
+fn shared_borders() {
+    for path in ["assets/map.bmp", "assets/holes.bmp", "assets/islands.bmp"] {
+        let img = bmp::open(path).unwrap();
+        let topology = load_topology(&img).unwrap();
+        check_arcs(&img, &topology);
+        check_arc_rings(&topology);
+    }
+}
@@ -0,0 +1,66 @@
+use bmp::{Image, Pixel};
+use bmpoly::{adjacency::load_adjacency, eu4::Definitions, polygon::{load_polygons, Polygon}, source::VoidColors, topology::load_topology};
+
+const BACKGROUND: (u8, u8, u8) = (0, 0, 0);
+const WEST: (u8, u8, u8) = (65, 194, 88);
+const EAST: (u8, u8, u8) = (194, 65, 182);
+
+fn fill(img: &mut Image, (x0, y0, x1, y1): (u32, u32, u32, u32), (r, g, b): (u8, u8, u8)) {
+    for y in y0..y1 {
+        for x in x0..x1 {
+            img.set_pixel(x, y, Pixel::new(r, g, b));
+        }
+    }
+}
+
+// Two provinces on a background, touching along a 4 pixel border, with a lake of background in the western one
+fn map() -> Image {
+    let mut img = Image::new(12, 8);
+    fill(&mut img, (0, 0, 12, 8), BACKGROUND);
+    fill(&mut img, (1, 2, 6, 6), WEST);
+    fill(&mut img, (6, 2, 10, 6), EAST);
+    fill(&mut img, (2, 3, 4, 5), BACKGROUND);
+    img
+}
+
+// Arcs are left out, as a void background has no border with the outside of the map and numbers them differently
+fn shapes(polygons: Vec<Polygon>) -> Vec<Polygon> {
+    polygons.into_iter()
+        .filter(|poly| poly.source_color != BACKGROUND)
+        .map(|poly| Polygon { arc_rings: Vec::new(), ..poly })
+        .collect()
+}
+
+// Void pixels border their neighbors just like a traced background, so the other polygons come out the same
+#[test]
+fn both_tracers_skip_void() {
+    let img = VoidColors::new(map(), [BACKGROUND]);
+
+    let traced = load_polygons(&img);
+    assert_eq!(shapes(traced.clone()), shapes(load_polygons(map())));
+
+    let topology = load_topology(&img).unwrap();
+    assert!(topology.rings.iter().all(|ring| ring.color != BACKGROUND));
+    let assembled = topology.polygons().unwrap();
+    assert_eq!(shapes(assembled.clone()), shapes(load_topology(map()).unwrap().polygons().unwrap()));
+
+    let areas: Vec<((u8, u8, u8), f32)> = assembled.iter()
+        .map(|poly| (poly.source_color, poly.parts.iter().map(|part| part.area).sum()))
+        .collect();
+    assert_eq!(areas, vec![(WEST, 16.0), (EAST, 16.0)]);
+    for polygons in [&traced, &assembled] {
+        let west = polygons.iter().find(|poly| poly.source_color == WEST).unwrap();
+        assert_eq!(west.parts[0].holes.len(), 1);
+    }
+}
+
+#[test]
+fn void_is_not_a_neighbor() {
+    let img = VoidColors::new(map(), [BACKGROUND]);
+    let adjacency = load_adjacency(&img, &Definitions::default()).unwrap();
+
+    assert_eq!(adjacency.neighbors.keys().copied().collect::<Vec<_>>(), vec![WEST, EAST]);
+    let neighbors = adjacency.neighbors(WEST);
+    assert_eq!(neighbors.len(), 1);
+    assert_eq!((neighbors[0].color, neighbors[0].border_length), (EAST, 4));
+}